@@ -1,5 +1,9 @@
-use clap::{Args, Parser, Subcommand};
+use crate::matrix::matrix_brightness::BrightnessSchedule;
+use crate::playlist::playlist_entry::PlaylistEntry;
+use crate::playlist::playlist_schedule::ScheduleRule;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 pub const MATRICKS_DEFAULT_FPS: &str = "30";
 pub const MATRICKS_DEFAULT_SERPENTINE: &str = "false";
@@ -11,6 +15,26 @@ pub const MATRICKS_DEFAULT_LOOP: &str = "false";
 pub const MATRICKS_DEFAULT_DMA_CHANNEL: &str = "10";
 pub const MATRICKS_DEFAULT_GPIO_PIN: &str = "10";
 pub const MATRICKS_DEFAULT_LED_SIGNAL_FREQ: &str = "800000";
+pub const MATRICKS_DEFAULT_OUTPUT: &str = "ws281x";
+pub const MATRICKS_DEFAULT_FIT: &str = "crop";
+pub const MATRICKS_DEFAULT_INSTANCES: &str = "fresh";
+pub const MATRICKS_DEFAULT_WATCH: &str = "false";
+pub const MATRICKS_DEFAULT_ORDER: &str = "sequential";
+pub const MATRICKS_DEFAULT_AVOID_LAST: &str = "0";
+pub const MATRICKS_DEFAULT_RECORD_PATH: &str = "matricks_recording";
+pub const MATRICKS_DEFAULT_OPC_ADDRESS: &str = "127.0.0.1:7890";
+pub const MATRICKS_DEFAULT_OPC_CHANNEL: &str = "0";
+pub const MATRICKS_DEFAULT_SACN_UNIVERSE: &str = "1";
+pub const MATRICKS_DEFAULT_SACN_PRIORITY: &str = "100";
+pub const MATRICKS_DEFAULT_SACN_SOURCE_NAME: &str = "Matricks";
+pub const MATRICKS_DEFAULT_ARTNET_NODE: &str = "255.255.255.255";
+pub const MATRICKS_DEFAULT_ARTNET_NET: &str = "0";
+pub const MATRICKS_DEFAULT_ARTNET_SUBNET: &str = "0";
+pub const MATRICKS_DEFAULT_ARTNET_UNIVERSE: &str = "0";
+pub const MATRICKS_DEFAULT_ARTNET_CHANNEL_OFFSET: &str = "0";
+pub const MATRICKS_DEFAULT_DDP_ADDRESS: &str = "127.0.0.1:4048";
+pub const MATRICKS_DEFAULT_RENDER_FRAMES: &str = "300";
+pub const MATRICKS_DEFAULT_RENDER_SCALE: &str = "10";
 
 #[derive(Parser)]
 #[command(author, version, about, long_about=None)]
@@ -38,6 +62,9 @@ pub enum MatricksSubcommand {
 
     /// Clear the matrix
     Clear(MatrixConfigurationArgs),
+
+    /// Render plugins to an animated GIF or APNG instead of driving the matrix
+    Render(RenderArgs),
 }
 
 /// Information needed to read a configuration file
@@ -56,6 +83,91 @@ pub struct ConfigurationFileWriteInfo {
     pub config_path: String,
 }
 
+/// Formats that plugins can be rendered to
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderFormat {
+    /// Animated GIF
+    Gif,
+
+    /// Animated PNG
+    Apng,
+}
+
+/// Ways that a plugin frame can be made to fit the matrix
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FitPolicy {
+    /// Skip the plugin if it returns a frame that does not match the matrix
+    Reject,
+
+    /// Cut off pixels outside of the matrix, and fill in missing pixels with black
+    #[default]
+    Crop,
+
+    /// Fill in missing pixels with black, but skip the plugin if its frame is larger than the matrix
+    Pad,
+
+    /// Scale the frame to the matrix, using the nearest pixel
+    Nearest,
+
+    /// Scale the frame to the matrix, blending between neighboring pixels
+    Bilinear,
+}
+
+/// Ways that plugin instances can be reused when the playlist loops
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceMode {
+    /// Load, compile and set up each plugin again on every pass through the playlist
+    #[default]
+    Fresh,
+
+    /// Keep each plugin running between passes, calling its optional resume function instead of setting it up again
+    Persistent,
+}
+
+/// Orders that the plugins in the playlist can be played in
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistOrder {
+    /// Play the plugins in the order they are listed
+    #[default]
+    Sequential,
+
+    /// Play every plugin once per pass, in a new random order each pass
+    Shuffle,
+
+    /// Pick each plugin at random, with plugins of a higher weight picked more often
+    Weighted,
+}
+
+/// Backends that Matricks can render the matrix to
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputKind {
+    /// WS281x LEDs connected to the GPIO of a Raspberry Pi
+    #[default]
+    Ws281x,
+
+    /// Raw RGBA frames and a JSON index, written to a directory
+    Record,
+
+    /// A live preview drawn in the terminal with truecolor half-blocks
+    Terminal,
+
+    /// An Open Pixel Control server, over TCP
+    Opc,
+
+    /// E1.31 (sACN) receivers, over UDP
+    Sacn,
+
+    /// An Art-Net node, over UDP
+    Artnet,
+
+    /// A DDP device such as a WLED controller, over UDP
+    Ddp,
+}
+
 /// Options relevant to setting up a LED controller
 #[derive(Serialize, Deserialize, Args, Clone)]
 pub struct LEDControllerArgs {
@@ -72,6 +184,126 @@ pub struct LEDControllerArgs {
     pub frequency: u32,
 }
 
+/// Options relevant to recording frames to disk
+#[derive(Serialize, Deserialize, Args, Clone)]
+pub struct RecordOutputArgs {
+    /// Directory to write recorded frames to when using the record output
+    #[arg(long, default_value = MATRICKS_DEFAULT_RECORD_PATH)]
+    pub record_path: String,
+}
+
+impl Default for RecordOutputArgs {
+    fn default() -> Self {
+        Self {
+            record_path: String::from(MATRICKS_DEFAULT_RECORD_PATH),
+        }
+    }
+}
+
+/// Options relevant to sending frames to an Open Pixel Control server
+#[derive(Serialize, Deserialize, Args, Clone)]
+pub struct OpcOutputArgs {
+    /// Address (host:port) of the OPC server to send frames to when using the opc output
+    #[arg(long, default_value = MATRICKS_DEFAULT_OPC_ADDRESS)]
+    pub opc_address: String,
+
+    /// OPC channel to send frames to. Channel 0 sends frames to every channel on the server.
+    #[arg(long, default_value = MATRICKS_DEFAULT_OPC_CHANNEL)]
+    pub opc_channel: u8,
+}
+
+impl Default for OpcOutputArgs {
+    fn default() -> Self {
+        Self {
+            opc_address: String::from(MATRICKS_DEFAULT_OPC_ADDRESS),
+            opc_channel: 0,
+        }
+    }
+}
+
+/// Options relevant to sending frames over E1.31 (sACN)
+#[derive(Serialize, Deserialize, Args, Clone)]
+pub struct SacnOutputArgs {
+    /// First universe to send frames to when using the sacn output. Frames that do not fit in one universe continue into the following universes.
+    #[arg(long, default_value = MATRICKS_DEFAULT_SACN_UNIVERSE, value_parser = clap::value_parser!(u16).range(1..=63999))]
+    pub sacn_universe: u16,
+
+    /// Priority of the sACN stream, from 0-200
+    #[arg(long, default_value = MATRICKS_DEFAULT_SACN_PRIORITY, value_parser = clap::value_parser!(u8).range(0..=200))]
+    pub sacn_priority: u8,
+
+    /// Source name to identify the sACN stream with
+    #[arg(long, default_value = MATRICKS_DEFAULT_SACN_SOURCE_NAME)]
+    pub sacn_source_name: String,
+
+    /// Send sACN packets to this IP address instead of multicasting them
+    #[arg(long)]
+    pub sacn_destination: Option<String>,
+}
+
+impl Default for SacnOutputArgs {
+    fn default() -> Self {
+        Self {
+            sacn_universe: 1,
+            sacn_priority: 100,
+            sacn_source_name: String::from(MATRICKS_DEFAULT_SACN_SOURCE_NAME),
+            sacn_destination: None,
+        }
+    }
+}
+
+/// Options relevant to sending frames over Art-Net
+#[derive(Serialize, Deserialize, Args, Clone)]
+pub struct ArtNetOutputArgs {
+    /// IP address of the Art-Net node to send frames to when using the artnet output. Frames are broadcast by default.
+    #[arg(long, default_value = MATRICKS_DEFAULT_ARTNET_NODE)]
+    pub artnet_node: String,
+
+    /// Net of the first universe to send frames to, from 0-127
+    #[arg(long, default_value = MATRICKS_DEFAULT_ARTNET_NET, value_parser = clap::value_parser!(u8).range(0..=127))]
+    pub artnet_net: u8,
+
+    /// Subnet of the first universe to send frames to, from 0-15
+    #[arg(long, default_value = MATRICKS_DEFAULT_ARTNET_SUBNET, value_parser = clap::value_parser!(u8).range(0..=15))]
+    pub artnet_subnet: u8,
+
+    /// First universe to send frames to, from 0-15. Frames that do not fit in one universe continue into the following universes.
+    #[arg(long, default_value = MATRICKS_DEFAULT_ARTNET_UNIVERSE, value_parser = clap::value_parser!(u8).range(0..=15))]
+    pub artnet_universe: u8,
+
+    /// Number of channels to skip at the start of the first universe, from 0-509
+    #[arg(long, default_value = MATRICKS_DEFAULT_ARTNET_CHANNEL_OFFSET, value_parser = clap::value_parser!(u16).range(0..=509))]
+    pub artnet_channel_offset: u16,
+}
+
+impl Default for ArtNetOutputArgs {
+    fn default() -> Self {
+        Self {
+            artnet_node: String::from(MATRICKS_DEFAULT_ARTNET_NODE),
+            artnet_net: 0,
+            artnet_subnet: 0,
+            artnet_universe: 0,
+            artnet_channel_offset: 0,
+        }
+    }
+}
+
+/// Options relevant to sending frames over DDP
+#[derive(Serialize, Deserialize, Args, Clone)]
+pub struct DdpOutputArgs {
+    /// Address (host:port) of the DDP device to send frames to when using the ddp output
+    #[arg(long, default_value = MATRICKS_DEFAULT_DDP_ADDRESS)]
+    pub ddp_address: String,
+}
+
+impl Default for DdpOutputArgs {
+    fn default() -> Self {
+        Self {
+            ddp_address: String::from(MATRICKS_DEFAULT_DDP_ADDRESS),
+        }
+    }
+}
+
 /// Options relevant to setting up a LED matrix
 #[derive(Serialize, Deserialize, Args, Clone)]
 pub struct MatrixConfigurationArgs {
@@ -107,16 +339,52 @@ pub struct MatrixConfigurationArgs {
     #[arg(short, long, default_value = MATRICKS_DEFAULT_BRIGHTNESS)]
     pub brightness: u8,
 
+    /// Curve that dims the matrix through the day. Only available from a configuration file.
+    #[arg(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness_schedule: Option<BrightnessSchedule>,
+
+    /// How to handle plugin frames that do not match the size of the matrix
+    #[arg(long, value_enum, default_value = MATRICKS_DEFAULT_FIT)]
+    #[serde(default)]
+    pub fit: FitPolicy,
+
+    /// Outputs to render the matrix to. Every frame is rendered to each output, e.g. "--output ws281x,terminal".
+    #[arg(long, value_enum, value_delimiter = ',', default_value = MATRICKS_DEFAULT_OUTPUT)]
+    #[serde(default = "default_outputs", deserialize_with = "deserialize_outputs")]
+    pub output: Vec<OutputKind>,
+
     #[command(flatten)]
     pub controller: LEDControllerArgs,
+
+    #[command(flatten)]
+    #[serde(default)]
+    pub record: RecordOutputArgs,
+
+    #[command(flatten)]
+    #[serde(default)]
+    pub opc: OpcOutputArgs,
+
+    #[command(flatten)]
+    #[serde(default)]
+    pub sacn: SacnOutputArgs,
+
+    #[command(flatten)]
+    #[serde(default)]
+    pub artnet: ArtNetOutputArgs,
+
+    #[command(flatten)]
+    #[serde(default)]
+    pub ddp: DdpOutputArgs,
 }
 
 /// Options relevant to setting up plugins
 #[derive(Serialize, Deserialize, Args, Clone)]
 pub struct PluginConfigurationArgs {
     /// Add a plugin at a given path to the playlist
-    #[arg(short, long, required = true)]
-    pub plugin: Vec<String>,
+    #[arg(short, long, required_unless_present = "receive")]
+    #[serde(default)]
+    pub plugin: Vec<PlaylistEntry>,
 
     /// Maximum time (in seconds) that a single plugin can run before moving on to the next one. No time limit by default.
     #[arg(short, long)]
@@ -133,6 +401,79 @@ pub struct PluginConfigurationArgs {
     /// Map a path on the host filesystem to a path on the plugin filesystem. Inputs should be of the form "DEST_PATH>HOST_PATH".
     #[arg(long)]
     pub map_path: Option<Vec<String>>,
+
+    /// Whether plugins are loaded fresh on every pass through the playlist, or kept running between passes
+    #[arg(long, value_enum, default_value = MATRICKS_DEFAULT_INSTANCES)]
+    #[serde(default)]
+    pub instances: InstanceMode,
+
+    /// Reload a plugin whenever its WASM file changes, without restarting Matricks
+    #[arg(long, default_value = MATRICKS_DEFAULT_WATCH)]
+    #[serde(default)]
+    pub watch: bool,
+
+    /// Order to play the plugins in the playlist in
+    #[arg(long, value_enum, default_value = MATRICKS_DEFAULT_ORDER)]
+    #[serde(default)]
+    pub order: PlaylistOrder,
+
+    /// Avoid playing any of the last N plugins again when the playlist is played in a random order
+    #[arg(long, default_value = MATRICKS_DEFAULT_AVOID_LAST)]
+    #[serde(default)]
+    pub avoid_last: usize,
+}
+
+/// Network protocols that Matricks can receive frames over
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveProtocol {
+    /// E1.31 (sACN)
+    Sacn,
+
+    /// Art-Net
+    Artnet,
+
+    /// DDP
+    Ddp,
+}
+
+/// The outputs to use when a configuration does not list any
+fn default_outputs() -> Vec<OutputKind> {
+    vec![OutputKind::default()]
+}
+
+/// Read the outputs of a configuration, which may be a single output or a list of outputs
+fn deserialize_outputs<'de, D>(deserializer: D) -> Result<Vec<OutputKind>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Outputs {
+        One(OutputKind),
+        Many(Vec<OutputKind>),
+    }
+
+    match Outputs::deserialize(deserializer)? {
+        Outputs::One(output) => Ok(vec![output]),
+        Outputs::Many(outputs) => Ok(outputs),
+    }
+}
+
+/// Options relevant to receiving frames from the network
+#[derive(Serialize, Deserialize, Args, Clone, Default)]
+pub struct ReceiverArgs {
+    /// Drive the matrix with frames received over the network using the given protocol, instead of running plugins
+    #[arg(long, value_enum)]
+    pub receive: Option<ReceiveProtocol>,
+
+    /// Port to listen for frames on. Uses the standard port of the protocol by default.
+    #[arg(long)]
+    pub receive_port: Option<u16>,
+
+    /// First sACN universe or Art-Net port-address to receive frames on. Frames that do not fit in one universe continue into the following universes. Defaults to 1 for sACN and 0 for Art-Net.
+    #[arg(long)]
+    pub receive_universe: Option<u16>,
 }
 
 /// Options relevant to setting up Matricks
@@ -143,4 +484,57 @@ pub struct MatricksConfigArgs {
 
     #[command(flatten)]
     pub plugin: PluginConfigurationArgs,
+
+    #[command(flatten)]
+    #[serde(default)]
+    pub receiver: ReceiverArgs,
+
+    /// Named playlists that the schedule can switch between. Only available from a configuration file.
+    #[arg(skip)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub playlists: BTreeMap<String, Vec<PlaylistEntry>>,
+
+    /// Rules choosing which named playlist plays at each time of day. Only available from a configuration file.
+    #[arg(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<ScheduleRule>,
+}
+
+/// Options relevant to rendering plugins to an animated image
+#[derive(Args, Clone)]
+pub struct RenderArgs {
+    /// Path to write the animated image to
+    pub image_path: String,
+
+    /// Format of the animated image. Picked from the file extension of the image path by default.
+    #[arg(long, value_enum)]
+    pub format: Option<RenderFormat>,
+
+    /// Maximum number of frames to render from each plugin
+    #[arg(short = 'n', long, default_value = MATRICKS_DEFAULT_RENDER_FRAMES)]
+    pub frames: usize,
+
+    /// Size of each LED in the rendered image, in pixels
+    #[arg(long, default_value = MATRICKS_DEFAULT_RENDER_SCALE)]
+    pub scale: usize,
+
+    #[command(flatten)]
+    pub matricks_config: MatricksConfigArgs,
+}
+
+/// Build a matrix configuration from command line arguments, as tests of the outputs need one
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments, without the program name
+///
+#[cfg(test)]
+pub(crate) fn test_matrix_config(args: &[&str]) -> MatrixConfigurationArgs {
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        matrix: MatrixConfigurationArgs,
+    }
+
+    TestArgs::parse_from(std::iter::once("matricks").chain(args.iter().copied())).matrix
 }
@@ -1,18 +1,29 @@
 use crate::clargs::MatricksConfigArgs;
 use crate::matrix::matrix_control::MatrixController;
-use std::collections::BTreeMap;
+use crate::matrix::matrix_state::MatrixUpdate;
+use crate::playlist::playlist_expand::expand_playlist;
+use crate::playlist::playlist_order::PlaylistOrderer;
+use crate::playlist::playlist_schedule::{PlaylistSchedule, MAIN_PLAYLIST};
+use crate::plugin::plugin_cache::PluginCache;
+use crate::plugin::plugin_context::{FrameContext, TeardownReason};
+use crate::plugin::plugin_host::PluginHost;
+use crate::receiver::receiver_protocol::receive_frames;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use std::ffi::OsStr;
-use std::fs;
-use std::path::Path;
-use std::str::from_utf8;
-use std::time::{Duration, Instant};
+/// How often to check if the WASM file of the current plugin has changed, when watching plugins
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
-use crate::path_map::PathMap;
-use crate::plugin::plugin_logs;
-use extism::manifest::Wasm;
-use extism::{Function, Manifest, Plugin, ValType};
-use serde_json::from_str;
+/// How often to check if Matricks has been asked to quit or the schedule has switched, while holding the last frame of a plugin
+const HOLD_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often to check if the schedule has switched to another playlist, while a plugin is playing
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait before looking for plugins again, when a pass through the playlist found none
+const EMPTY_PLAYLIST_RETRY: Duration = Duration::from_secs(1);
 
 /// Core Matricks functionality
 ///
@@ -25,285 +36,321 @@ pub fn matricks_core(config: MatricksConfigArgs) {
     let target_frame_time_ms =
         Duration::from_nanos((1_000_000_000.0 / config.matrix.fps).round() as u64);
 
-    // Create the config
-    let mut matricks_config: BTreeMap<String, Option<String>> = BTreeMap::new();
-    matricks_config.insert(
-        String::from("width"),
-        Some(format!("{}", config.matrix.width)),
-    );
-    matricks_config.insert(
-        String::from("height"),
-        Some(format!("{}", config.matrix.height)),
-    );
-    matricks_config.insert(
-        String::from("target_fps"),
-        Some(format!("{}", config.matrix.fps)),
-    );
-    matricks_config.insert(
-        String::from("serpentine"),
-        Some(format!("{}", config.matrix.serpentine)),
-    );
-    matricks_config.insert(
-        String::from("brightness"),
-        Some(format!("{}", config.matrix.brightness)),
-    );
-
-    // Setup the host functions
-    let plugin_debug_log_function = Function::new(
-        "matricks_debug",
-        [ValType::I64],
-        [],
-        None,
-        plugin_logs::plugin_debug_log,
-    );
-    let plugin_info_log_function = Function::new(
-        "matricks_info",
-        [ValType::I64],
-        [],
-        None,
-        plugin_logs::plugin_info_log,
-    );
-    let plugin_warn_log_function = Function::new(
-        "matricks_warn",
-        [ValType::I64],
-        [],
-        None,
-        plugin_logs::plugin_warn_log,
-    );
-    let plugin_error_log_function = Function::new(
-        "matricks_error",
-        [ValType::I64],
-        [],
-        None,
-        plugin_logs::plugin_error_log,
-    );
-    let plugin_functions = [
-        plugin_debug_log_function,
-        plugin_info_log_function,
-        plugin_warn_log_function,
-        plugin_error_log_function,
-    ];
-
-    // Process user-supplied path mappings
-    let mut path_mappings: Vec<PathMap> = vec![];
-    match config.plugin.map_path {
-        None => { /* Do nothing */ }
-        Some(path_map_strings) => {
-            for path_map_string in path_map_strings {
-                match PathMap::from_string(path_map_string.clone()) {
-                    Ok(path_map) => {
-                        log::info!(
-                            "Mapping local filesystem path \"{}\" to plugin filesystem path \"{}\"",
-                            path_map.from,
-                            path_map.to
-                        );
-                        path_mappings.push(path_map);
-                    }
-                    Err(_) => {
-                        log::warn!("Unable to process path mapping \"{path_map_string}\". This mapping will be ignored.");
-                    }
-                };
-            }
+    // Entries cannot run their plugins any faster than the matrix is updated
+    let playlist_entries = config.plugin.plugin.iter();
+    for playlist_entry in playlist_entries.chain(config.playlists.values().flatten()) {
+        if let Some(fps) = playlist_entry.fps.filter(|fps| *fps > config.matrix.fps) {
+            log::warn!(
+                "The playlist entry for \"{}\" sets an fps of {fps}, which is faster than the matrix. It will run at {} fps instead.",
+                playlist_entry.path,
+                config.matrix.fps
+            );
         }
     }
 
     // Create a new matrix controller object
-    let mut matrix = MatrixController::new(
-        (config.matrix.width, config.matrix.height),
-        config.matrix.serpentine,
-        config.matrix.mirror_horizontal,
-        config.matrix.mirror_vertical,
-        config.matrix.vertical,
-        config.matrix.brightness,
-        config.matrix.controller.gpio,
-        config.matrix.controller.dma,
-        config.matrix.controller.frequency,
-    );
+    let mut matrix = MatrixController::new(&config.matrix);
 
     // Start the matrix controller
     match matrix.start() {
         Ok(_) => {}
         Err(_) => {
             log::error!("Failed to start new matrix controller.");
+            log::info!("Quitting Matricks.");
+            return;
+        }
+    }
+
+    // Stop on Ctrl-C, so that the current plugin can be torn down and the matrix cleared before Matricks quits
+    // A second Ctrl-C quits straight away, in case a plugin or output is stuck
+    let shutdown_requested = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&shutdown_requested);
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            log::warn!("Received a second request to quit. Quitting without cleaning up.");
+            std::process::exit(130);
+        }
+        log::info!("Cleaning up before quitting. Press Ctrl-C again to quit straight away.");
+    }) {
+        log::warn!("Failed to set up the Ctrl-C handler. Matricks will not clean up when it is interrupted.");
+        log::debug!("Failed with the following error: {e:?}");
+    }
+
+    match config.receiver.receive {
+        // Show frames from the network until Matricks is asked to quit
+        Some(protocol) => receive_frames(protocol, &config, &mut matrix, &shutdown_requested),
+        None => play_plugins(
+            &config,
+            &mut matrix,
+            target_frame_time_ms,
+            &shutdown_requested,
+        ),
+    }
+
+    log::info!("Quitting Matricks.");
+
+    // Stop the update thread
+    match matrix.stop() {
+        Ok(_) => {}
+        Err(_) => {
+            log::error!("Failed to stop matrix controller.");
         }
     }
 
+    log::info!("Done.");
+}
+
+/// Run each plugin in turn, sending its updates to the matrix
+///
+/// # Arguments
+///
+/// * `config` - Information about how Matricks should run
+/// * `matrix` - The matrix controller to send plugin updates to
+/// * `target_frame_time_ms` - The time between plugin updates
+/// * `shutdown_requested` - Set when Matricks has been asked to quit
+///
+fn play_plugins(
+    config: &MatricksConfigArgs,
+    matrix: &mut MatrixController,
+    target_frame_time_ms: Duration,
+    shutdown_requested: &AtomicBool,
+) {
+    // Setup everything the plugins need from the host
+    let plugin_host = PluginHost::new(config);
+    let mut plugin_cache = PluginCache::new(config);
+    let mut playlist_orderer = PlaylistOrderer::new(&config.plugin);
+    let playlist_schedule = PlaylistSchedule::new(&config.schedule, &config.playlists);
+
+    // The scheduled playlist of the last pass, which is unknown before the first pass
+    let mut last_scheduled_playlist: Option<Option<String>> = None;
+
     // The main loop, which is run infinitely if the loop command line flag is set
     'main_loop: loop {
-        ////// PLUGIN LOOP
-        for plugin_path in &config.plugin.plugin {
-            // Get the plugin data at the given path
-            let plugin_data = match fs::read(plugin_path) {
-                Ok(data) => {data}
-                Err(e) => {
-                    log::error!("Failed to read plugin data at path '{plugin_path}'");
-                    log::debug!("Failed with error: {e}");
-                    log::warn!("This plugin will be skipped.");
-                    continue;
+        // Play the playlist chosen by the schedule, or the main playlist if no rule is active
+        let scheduled_playlist = playlist_schedule.active_playlist().map(str::to_string);
+        let (playlist_name, playlist_entries) = match &scheduled_playlist {
+            Some(name) => (name.as_str(), &config.playlists[name]),
+            None => (MAIN_PLAYLIST, &config.plugin.plugin),
+        };
+        let playlist_switched = last_scheduled_playlist.as_ref() != Some(&scheduled_playlist);
+        if playlist_switched && !playlist_schedule.is_empty() {
+            match &scheduled_playlist {
+                Some(name) => log::info!("Switching to playlist \"{name}\"."),
+                None => log::info!("Switching to the main playlist."),
+            }
+        }
+        last_scheduled_playlist = Some(scheduled_playlist.clone());
+
+        // Find the plugins in the playlist again on every pass, so that new plugins are picked up
+        let playlist = playlist_orderer.next_pass(expand_playlist(playlist_name, playlist_entries));
+        if playlist.is_empty() {
+            // A scheduled playlist with nothing to play keeps the matrix dark
+            if scheduled_playlist.is_some() {
+                let dark_state = vec![vec![[0; 4]; config.matrix.width]; config.matrix.height];
+                if matrix.update(dark_state).is_err() {
+                    log::error!("Failed to update matrix controller.");
+                    break 'main_loop;
                 }
-            };
+            }
 
-            // Pull the filename out from the plugin path
-            let plugin_name = Path::new(plugin_path)
-                .file_name()
-                .unwrap_or(OsStr::new(plugin_path))
-                .to_str()
-                .unwrap_or(plugin_path);
+            // A scheduled playlist can be left without entries on purpose, so only warn about one with entries once
+            if scheduled_playlist.is_none() || (playlist_switched && !playlist_entries.is_empty()) {
+                log::warn!("No plugins were found in the playlist.");
+            }
+        }
 
-            // Make a new manifest for the plugin
-            let mut manifest = Manifest::new([Wasm::data(plugin_data)]);
+        ////// PLUGIN LOOP
+        for playlist_item in &playlist {
+            let playlist_entry = &playlist_item.entry;
 
-            // Add the allowed hosts to the manifest
-            for host in config.plugin.allow_host.clone().unwrap_or(vec![]) {
-                log::debug!("Adding host \"{host}\" to the manifest.");
-                manifest = manifest.with_allowed_host(host);
+            // Stop before starting another plugin if Matricks has been asked to quit
+            if shutdown_requested.load(Ordering::SeqCst) {
+                log::info!("Received a request to quit.");
+                break 'main_loop;
             }
 
-            // Add the path mappings to the manifest
-            for path_map in path_mappings.clone() {
-                log::debug!(
-                    "Adding mapping from \"{}\" to \"{}\" to the manifest.",
-                    path_map.from,
-                    path_map.to
-                );
-                manifest = manifest.with_allowed_path(path_map.from, path_map.to);
+            // Start the new playlist straight away if the schedule has switched to it
+            if playlist_schedule.active_playlist() != scheduled_playlist.as_deref() {
+                continue 'main_loop;
             }
 
-            // Make a new instance of the plugin
-            log::info!("Starting plugin \"{plugin_name}\".");
-            let plugin = match Plugin::create_with_manifest(
-                &manifest,
-                plugin_functions.clone(),
-                true,
-            ) {
-                Ok(plugin) => plugin,
-                Err(e) => {
-                    log::error!("Unable to instantiate plugin \"{plugin_name}\".");
-                    log::debug!("Received the following error while attempting to instantiate the plugin: {e:?}");
-                    log::warn!("This plugin will be skipped.");
-                    continue;
-                }
-            };
-
-            // Apply the config to the plugin
-            let mut plugin = match plugin.with_config(&matricks_config) {
+            // Get an instance of the plugin that is ready to be updated
+            let mut plugin = match plugin_cache.start(&plugin_host, playlist_item) {
                 Ok(plugin) => plugin,
-                Err(e) => {
-                    log::error!("Unable to apply configuration to plugin \"{plugin_name}\".");
-                    log::debug!("Received the following error while attempting to instantiate the plugin: {e:?}");
+                Err(_) => {
                     log::warn!("This plugin will be skipped.");
                     continue;
                 }
             };
+            let mut plugin_name = plugin.name().to_string();
 
-            // Call setup function of current active plugin
-            let _setup_result = match plugin.call("setup", "") {
-                Ok(result) => {
-                    log::info!("Successfully set up plugin \"{plugin_name}\".");
-                    result
-                }
-                Err(e) => {
-                    log::warn!("Unable to set up plugin \"{plugin_name}\".");
-                    log::debug!("Received the following error while setting up the plugin: {e:?}");
-                    &[]
-                }
+            // Use any framerate or brightness that the entry overrides
+            let plugin_frame_time = match playlist_entry.fps {
+                Some(_) => Duration::from_nanos(
+                    (1_000_000_000.0 / playlist_entry.frame_rate(config.matrix.fps)).round() as u64,
+                ),
+                None => target_frame_time_ms,
             };
+            let plugin_brightness = playlist_entry
+                .brightness
+                .unwrap_or(config.matrix.brightness);
+            matrix.set_brightness(plugin_brightness);
 
             // Mark the time when this plugin started its update loop
-            let plugin_start_time = Instant::now();
+            let mut plugin_start_time = Instant::now();
 
             // Setup the last frame time variable
             let mut last_frame_time = Instant::now();
 
-            // Run an update every frame
-            'update_loop: loop {
+            // Keep track of the updates given to the plugin, so that it can be told about each frame
+            let time_limit = playlist_entry
+                .time_limit
+                .or(config.plugin.time_limit)
+                .map(Duration::from_secs);
+            let mut frame_index: u64 = 0;
+            let mut last_update_time: Option<Instant> = None;
+
+            // Setup the last time the plugin's file was checked for changes
+            let mut last_watch_time = Instant::now();
+
+            // Setup the last time the schedule was checked
+            let mut last_schedule_time = Instant::now();
+
+            // Run an update every frame, until the plugin has to leave the playlist
+            let mut teardown_reason = 'update_loop: loop {
+                // Stop playing if Matricks has been asked to quit
+                if shutdown_requested.load(Ordering::SeqCst) {
+                    log::info!("Received a request to quit.");
+                    break 'update_loop TeardownReason::Shutdown;
+                }
+
+                // Reload the plugin if its file has changed, starting its turn again with the new version
+                if config.plugin.watch && last_watch_time.elapsed() >= WATCH_INTERVAL {
+                    last_watch_time = Instant::now();
+                    if plugin.source_changed() && plugin_host.reload(playlist_entry, &mut plugin) {
+                        plugin_name = plugin.name().to_string();
+                        plugin_start_time = Instant::now();
+                        frame_index = 0;
+                        last_update_time = None;
+                    }
+                }
+
+                // Stop playing if the schedule has switched to another playlist
+                if !playlist_schedule.is_empty()
+                    && last_schedule_time.elapsed() >= SCHEDULE_CHECK_INTERVAL
+                {
+                    last_schedule_time = Instant::now();
+                    if playlist_schedule.active_playlist() != scheduled_playlist.as_deref() {
+                        break 'update_loop TeardownReason::Schedule;
+                    }
+                }
+
                 // Move on to the next plugin if the plugin time limit has been exceeded
-                match config.plugin.time_limit {
-                    None => { /* There is no time limit, so do nothing */ }
-                    Some(time_limit) => {
-                        // Move on to the next plugin if this plugin has been running longer than the time limit
-                        if Instant::now() - plugin_start_time > Duration::from_secs(time_limit) {
-                            break 'update_loop;
-                        }
+                if let Some(time_limit) = time_limit {
+                    // Move on to the next plugin if this plugin has been running longer than the time limit
+                    if Instant::now() - plugin_start_time > time_limit {
+                        break 'update_loop TeardownReason::TimeLimit;
                     }
                 }
 
                 // Call the update function if a frame has passed
-                if (Instant::now() - last_frame_time) >= target_frame_time_ms {
+                if (Instant::now() - last_frame_time) >= plugin_frame_time {
                     // Reset the last frame time
                     last_frame_time = Instant::now();
 
-                    // Call the update function
-                    match plugin.call("update", "") {
-                        Ok(json_result_utf8) => {
-                            // Convert the result form utf8 to &str
-                            let json_result_str = match from_utf8(json_result_utf8) {
-                                Ok(s) => s,
-                                Err(_) => {
-                                    log::warn!("Received invalid UTF-8 result from plugin \"{plugin_name}\"");
-                                    log::warn!("This plugin will be skipped.");
-                                    break 'update_loop;
-                                }
-                            };
-
-                            // Pull the next matrix state from the plugin's response
-                            let new_matrix_state =
-                                match from_str::<Option<Vec<Vec<[u8; 4]>>>>(json_result_str) {
-                                    Ok(matrix_state) => matrix_state,
-                                    Err(_) => {
-                                        log::warn!(
-                                        "Received malformed update from plugin \"{plugin_name}\""
-                                    );
-                                        log::warn!("This plugin will be skipped.");
-                                        break 'update_loop;
-                                    }
-                                };
-
-                            let new_matrix_state = match new_matrix_state {
-                                None => {
-                                    log::info!("Done with plugin \"{plugin_name}\".");
-                                    break 'update_loop;
-                                }
-                                Some(new_matrix_state) => {new_matrix_state}
-                            };
-
-                            match matrix.update(new_matrix_state) {
-                                Ok(_) => { /* Do nothing, the new state sent without issue */
-                                }
-                                Err(_) => {
-                                    log::error!("Failed to update matrix controller.");
-                                    break 'main_loop;
-                                }
-                            }
+                    // Tell the plugin about the frame it is drawing
+                    let update_time = Instant::now();
+                    let frame_context = FrameContext::new(
+                        frame_index,
+                        update_time - plugin_start_time,
+                        last_update_time.map_or(Duration::ZERO, |last| update_time - last),
+                        time_limit,
+                        SystemTime::now(),
+                    );
+                    frame_index += 1;
+                    last_update_time = Some(update_time);
+
+                    // Pull the next matrix update from the plugin
+                    let matrix_update = match plugin.update(&frame_context) {
+                        Ok(Some(matrix_update)) => matrix_update,
+                        Ok(None) => {
+                            log::info!("Done with plugin \"{plugin_name}\".");
+                            break 'update_loop TeardownReason::Finished;
                         }
-                        Err(e) => {
-                            log::error!(
-                                "Unable to retrieve state update from plugin \"{plugin_name}\""
-                            );
-                            log::debug!("Received the following error while retrieving state update from plugin: {e:?}");
+                        Err(_) => {
                             log::warn!("This plugin will be skipped.");
-                            break 'update_loop;
+                            break 'update_loop TeardownReason::Error;
                         }
                     };
+
+                    let update_result = match matrix_update {
+                        MatrixUpdate::Full(new_matrix_state) => matrix.update(new_matrix_state),
+                        MatrixUpdate::Delta(changes) => matrix.apply_changes(&changes),
+                    };
+                    match update_result {
+                        Ok(_) => { /* Do nothing, the new state sent without issue */ }
+                        Err(_) => {
+                            log::error!("Failed to update matrix controller.");
+                            break 'update_loop TeardownReason::Shutdown;
+                        }
+                    }
                 }
+            };
+
+            // Hold the last frame if the plugin finished before its minimum duration
+            if let (TeardownReason::Finished, Some(min_duration)) =
+                (teardown_reason, playlist_entry.min_duration)
+            {
+                let min_duration = Duration::from_secs(min_duration);
+                if plugin_start_time.elapsed() < min_duration {
+                    log::info!("Holding the last frame of plugin \"{plugin_name}\" until it has been shown for {min_duration:?}.");
+                }
+                while plugin_start_time.elapsed() < min_duration {
+                    if shutdown_requested.load(Ordering::SeqCst) {
+                        log::info!("Received a request to quit.");
+                        teardown_reason = TeardownReason::Shutdown;
+                        break;
+                    }
+
+                    // Stop holding if the schedule has switched to another playlist
+                    if !playlist_schedule.is_empty()
+                        && last_schedule_time.elapsed() >= SCHEDULE_CHECK_INTERVAL
+                    {
+                        last_schedule_time = Instant::now();
+                        if playlist_schedule.active_playlist() != scheduled_playlist.as_deref() {
+                            teardown_reason = TeardownReason::Schedule;
+                            break;
+                        }
+                    }
+
+                    thread::sleep(HOLD_CHECK_INTERVAL);
+                }
+            }
+
+            // Keep the plugin for the next pass, or give it a chance to clean up before it is dropped
+            plugin_cache.stop(playlist_item, plugin, teardown_reason);
+
+            // Nothing else can be played once Matricks is shutting down
+            if matches!(teardown_reason, TeardownReason::Shutdown) {
+                break 'main_loop;
             }
         }
 
-        // Break if the loop flag is not set
-        if !config.plugin.loop_plugins {
+        // Break if the loop flag is not set, unless a schedule has to keep switching playlists
+        if !config.plugin.loop_plugins && playlist_schedule.is_empty() {
             break 'main_loop;
         }
-    }
-
-    log::info!("Quitting Matricks.");
 
-    // Stop the update thread
-    match matrix.stop() {
-        Ok(_) => {}
-        Err(_) => {
-            log::error!("Failed to stop matrix controller.");
+        // Avoid busily searching for plugins while there are none to play
+        if playlist.is_empty() {
+            thread::sleep(EMPTY_PLAYLIST_RETRY);
+            if shutdown_requested.load(Ordering::SeqCst) {
+                log::info!("Received a request to quit.");
+                break 'main_loop;
+            }
         }
     }
 
-    log::info!("Done.");
+    // Let any plugins that were waiting for their next turn clean up
+    plugin_cache.clear(TeardownReason::Shutdown);
 }
@@ -2,14 +2,18 @@ mod clargs;
 mod core;
 mod path_map;
 mod matrix;
+mod output;
+mod playlist;
 mod plugin;
+mod receiver;
+mod render;
 
 use crate::clargs::{MatricksArgs, MatricksSubcommand};
 use crate::core::matricks_core;
 
-use crate::matrix::matrix_control::clear_matrix;
+use crate::output::output_sink::new_output_sink;
+use crate::render::matricks_render;
 use clap::Parser;
-use rs_ws281x::{ChannelBuilder, ControllerBuilder, StripType};
 use std::{env, fs};
 
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
@@ -97,7 +101,6 @@ fn main() {
                 Ok(_) => {
                     log::info!("Successfully wrote matrix configuration to configuration file at path \"{}\"", info.config_path);
                     log::info!("Quitting Matricks.");
-                    return;
                 }
                 Err(e) => {
                     log::error!(
@@ -106,49 +109,39 @@ fn main() {
                     );
                     log::debug!("Received the following error while attempting to write matrix configuration to file: {e:?}");
                     log::info!("Quitting Matricks.");
-                    return;
                 }
             };
         }
         MatricksSubcommand::Clear(matrix_config) => {
             log::info!("Clearing the matrix.");
 
-            // Make an LED controller
-            let mut controller = match ControllerBuilder::new()
-                .freq(matrix_config.controller.frequency)
-                .dma(matrix_config.controller.dma as i32)
-                .channel(
-                    0, // channel index
-                    ChannelBuilder::new()
-                        .pin(matrix_config.controller.gpio as i32)
-                        .count((matrix_config.width * matrix_config.height) as i32)
-                        .strip_type(StripType::Ws2812)
-                        .brightness(matrix_config.brightness)
-                        .build(),
-                )
-                .build()
-            {
-                Ok(c) => c,
-                Err(e) => {
-                    log::error!("Failed to create LED controller.");
-                    log::debug!("Received the following error while attempting to create LED controller: {e:?}");
+            // Make an output for the matrix
+            let mut output = match new_output_sink(&matrix_config) {
+                Ok(output) => output,
+                Err(_) => {
+                    log::error!("Failed to create matrix output.");
                     log::info!("Quitting Matricks.");
                     return;
                 }
             };
 
-            // Clear the matrix using the controller we just made
-            match clear_matrix(&mut controller) {
+            // Clear the matrix using the output we just made
+            match output.clear() {
                 Ok(_) => {
                     log::info!("Successfully cleared matrix.");
                     log::info!("Quitting Matricks.");
                 }
-                Err(e) => {
+                Err(_) => {
                     log::error!("Failed to clear matrix.");
-                    log::debug!("Received the following error while clear matrix: {e:?}");
                     log::info!("Quitting Matricks.");
                 }
             }
         }
+        MatricksSubcommand::Render(render_args) => {
+            log::info!("Rendering plugins to an animated image.");
+
+            // Render the plugins using the supplied config
+            matricks_render(render_args);
+        }
     };
 }
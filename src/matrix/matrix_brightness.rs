@@ -0,0 +1,371 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Number of seconds in a day
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Altitude of the sun at sunrise and sunset, in degrees, allowing for refraction and the size of the sun
+const SUN_ALTITUDE: f64 = -0.833;
+
+/// Tilt of the earth's axis, in degrees
+const EARTH_TILT: f64 = 23.4397;
+
+/// Format of the clock times in a brightness schedule
+const TIME_FORMAT: &str = "%H:%M";
+
+/// A curve that dims the matrix through the day
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BrightnessSchedule {
+    /// Latitude of the matrix, in degrees north. Needed for points at sunrise or sunset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+
+    /// Longitude of the matrix, in degrees east. Needed for points at sunrise or sunset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+
+    /// The points of the curve. The brightness changes linearly from one point to the next.
+    pub points: Vec<BrightnessPoint>,
+}
+
+/// The brightness of the matrix at one time of day
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BrightnessPoint {
+    /// Time of day of this point
+    pub time: BrightnessTime,
+
+    /// Number of minutes to move this point by, so that it can be placed before or after sunrise or sunset
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub offset: i64,
+
+    /// Brightness at this point, from 0-255, as a fraction of the brightness of the matrix or plugin
+    pub brightness: u8,
+}
+
+/// Check if an offset is zero, so that it can be left out of saved configuration files
+fn is_zero(offset: &i64) -> bool {
+    *offset == 0
+}
+
+/// The time of day of a brightness point, written as "HH:MM", "sunrise" or "sunset"
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(try_from = "String", into = "String")]
+pub enum BrightnessTime {
+    /// A fixed time in the local timezone
+    Clock(NaiveTime),
+
+    /// The time that the sun rises
+    Sunrise,
+
+    /// The time that the sun sets
+    Sunset,
+}
+
+impl TryFrom<String> for BrightnessTime {
+    type Error = String;
+
+    fn try_from(time: String) -> Result<Self, Self::Error> {
+        match time.to_lowercase().as_str() {
+            "sunrise" => Ok(Self::Sunrise),
+            "sunset" => Ok(Self::Sunset),
+            _ => NaiveTime::parse_from_str(&time, TIME_FORMAT)
+                .map(Self::Clock)
+                .map_err(|_| {
+                    format!(
+                        "\"{time}\" is not \"sunrise\", \"sunset\" or a time in the form \"HH:MM\""
+                    )
+                }),
+        }
+    }
+}
+
+impl From<BrightnessTime> for String {
+    fn from(time: BrightnessTime) -> Self {
+        match time {
+            BrightnessTime::Clock(time) => time.format(TIME_FORMAT).to_string(),
+            BrightnessTime::Sunrise => String::from("sunrise"),
+            BrightnessTime::Sunset => String::from("sunset"),
+        }
+    }
+}
+
+/// Works out how much to dim the matrix at any time, following a brightness schedule
+pub(crate) struct BrightnessCurve {
+    /// The schedule that the curve follows
+    schedule: BrightnessSchedule,
+
+    /// The day that the points were last placed on
+    day: Option<NaiveDate>,
+
+    /// The points placed on that day, as seconds after midnight and brightness, sorted by time
+    day_points: Vec<(f64, u8)>,
+}
+
+impl BrightnessCurve {
+    /// Create a brightness curve from a schedule
+    ///
+    /// Points at sunrise or sunset are ignored if the schedule has no location.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - The brightness schedule from the matrix configuration
+    ///
+    pub(crate) fn new(schedule: &BrightnessSchedule) -> Self {
+        let mut schedule = schedule.clone();
+        let has_location = schedule.latitude.is_some() && schedule.longitude.is_some();
+        if !has_location
+            && schedule
+                .points
+                .iter()
+                .any(|point| !matches!(point.time, BrightnessTime::Clock(_)))
+        {
+            log::warn!("The brightness schedule needs a latitude and longitude to use sunrise and sunset. These points will be ignored.");
+            schedule
+                .points
+                .retain(|point| matches!(point.time, BrightnessTime::Clock(_)));
+        }
+
+        Self {
+            schedule,
+            day: None,
+            day_points: vec![],
+        }
+    }
+
+    /// Get the brightness of the curve at a time, from 0-255
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time to get the brightness at
+    ///
+    pub(crate) fn brightness_at(&mut self, now: DateTime<Local>) -> u8 {
+        // Place the points again once a day, since sunrise and sunset move
+        let today = now.date_naive();
+        if self.day != Some(today) {
+            self.day_points = self.place_points(today);
+            self.day = Some(today);
+        }
+
+        let (first, last) = match (self.day_points.first(), self.day_points.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return u8::MAX,
+        };
+
+        // Find the points on either side of now, wrapping around midnight
+        let seconds = now.num_seconds_from_midnight() as f64;
+        let previous = self
+            .day_points
+            .iter()
+            .rev()
+            .find(|(time, _)| *time <= seconds)
+            .copied()
+            .unwrap_or(last);
+        let next = self
+            .day_points
+            .iter()
+            .find(|(time, _)| *time > seconds)
+            .copied()
+            .unwrap_or(first);
+
+        // Blend linearly from the previous point to the next one
+        let span = (next.0 - previous.0).rem_euclid(SECONDS_PER_DAY);
+        if span == 0.0 {
+            return previous.1;
+        }
+        let progress = (seconds - previous.0).rem_euclid(SECONDS_PER_DAY) / span;
+        (previous.1 as f64 + (next.1 as f64 - previous.1 as f64) * progress).round() as u8
+    }
+
+    /// Work out when each point of the schedule falls on a day
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - The day to place the points on
+    ///
+    fn place_points(&self, day: NaiveDate) -> Vec<(f64, u8)> {
+        let sun_times = match (self.schedule.latitude, self.schedule.longitude) {
+            (Some(latitude), Some(longitude)) => sun_times(day, latitude, longitude)
+                .map(|(sunrise, sunset)| (local_time(sunrise), local_time(sunset))),
+            _ => None,
+        };
+        match sun_times {
+            Some((sunrise, sunset)) => log::info!(
+                "Sunrise is at {} and sunset is at {} today.",
+                sunrise.format(TIME_FORMAT),
+                sunset.format(TIME_FORMAT)
+            ),
+            None if self.schedule.latitude.is_some() && self.schedule.longitude.is_some() => {
+                log::info!("The sun does not rise or set today. Points at sunrise and sunset will be ignored.")
+            }
+            None => { /* Do nothing */ }
+        }
+
+        let mut day_points: Vec<(f64, u8)> = self
+            .schedule
+            .points
+            .iter()
+            .filter_map(|point| {
+                let time = match point.time {
+                    BrightnessTime::Clock(time) => time,
+                    BrightnessTime::Sunrise => sun_times?.0,
+                    BrightnessTime::Sunset => sun_times?.1,
+                };
+                let seconds = time.num_seconds_from_midnight() as f64 + point.offset as f64 * 60.0;
+                Some((seconds.rem_euclid(SECONDS_PER_DAY), point.brightness))
+            })
+            .collect();
+        day_points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        day_points
+    }
+}
+
+/// Get the time of day in the local timezone of a moment
+///
+/// # Arguments
+///
+/// * `time` - The moment to get the local time of day of
+///
+fn local_time(time: DateTime<Utc>) -> NaiveTime {
+    time.with_timezone(&Local).time()
+}
+
+/// Calculate the moments of sunrise and sunset on a day
+///
+/// Uses the sunrise equation, which is accurate to within a minute or two away from the poles.
+/// Returns `None` if the sun does not rise or set on the day.
+///
+/// # Arguments
+///
+/// * `day` - The day to calculate sunrise and sunset for
+/// * `latitude` - Latitude of the matrix, in degrees north
+/// * `longitude` - Longitude of the matrix, in degrees east
+///
+fn sun_times(
+    day: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let radians = |degrees: f64| degrees * PI / 180.0;
+
+    // Count the days from noon on the first of January 2000, and move to the mean solar noon of the matrix
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let days = (day - epoch).num_days() as f64 + 0.0008;
+    let mean_solar_noon = days - longitude / 360.0;
+
+    // Find where the earth is in its orbit, and when the sun is highest
+    let mean_anomaly = (357.5291 + 0.98560028 * mean_solar_noon).rem_euclid(360.0);
+    let center = 1.9148 * radians(mean_anomaly).sin()
+        + 0.0200 * radians(2.0 * mean_anomaly).sin()
+        + 0.0003 * radians(3.0 * mean_anomaly).sin();
+    let ecliptic_longitude = (mean_anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let solar_transit = mean_solar_noon + 0.0053 * radians(mean_anomaly).sin()
+        - 0.0069 * radians(2.0 * ecliptic_longitude).sin();
+
+    // Find how far the sun is from noon when it crosses the horizon
+    let declination = (radians(ecliptic_longitude).sin() * radians(EARTH_TILT).sin()).asin();
+    let hour_angle_cos = (radians(SUN_ALTITUDE).sin()
+        - radians(latitude).sin() * declination.sin())
+        / (radians(latitude).cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&hour_angle_cos) {
+        return None;
+    }
+    let hour_angle = hour_angle_cos.acos().to_degrees();
+
+    // Turn the days from the epoch into moments
+    let noon_epoch = Utc.from_utc_datetime(&epoch.and_hms_opt(12, 0, 0)?);
+    let moment =
+        |days: f64| noon_epoch + Duration::milliseconds((days * SECONDS_PER_DAY * 1000.0) as i64);
+    Some((
+        moment(solar_transit - hour_angle / 360.0),
+        moment(solar_transit + hour_angle / 360.0),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a brightness schedule from clock times and brightnesses
+    fn test_curve(points: &[(&str, u8)]) -> BrightnessCurve {
+        BrightnessCurve::new(&BrightnessSchedule {
+            latitude: None,
+            longitude: None,
+            points: points
+                .iter()
+                .map(|(time, brightness)| BrightnessPoint {
+                    time: BrightnessTime::try_from(time.to_string()).unwrap(),
+                    offset: 0,
+                    brightness: *brightness,
+                })
+                .collect(),
+        })
+    }
+
+    /// Get a local time in the middle of January, away from any daylight saving change
+    fn test_time(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        let time = NaiveDate::from_ymd_opt(2024, 1, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, 0))
+            .unwrap();
+        Local.from_local_datetime(&time).single().unwrap()
+    }
+
+    /// Check that a moment is within two minutes of a UTC time on a day
+    fn assert_near(moment: DateTime<Utc>, day: NaiveDate, hour: u32, minute: u32) {
+        let expected = Utc.from_utc_datetime(&day.and_hms_opt(hour, minute, 0).unwrap());
+        let difference = (moment - expected).num_seconds().abs();
+        assert!(difference <= 120, "{moment} is not near {expected}");
+    }
+
+    #[test]
+    fn sun_times_match_published_times() {
+        // London on the summer solstice of 2024: sunrise at 03:43 and sunset at 20:21 UTC
+        let day = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let (sunrise, sunset) = sun_times(day, 51.5074, -0.1278).unwrap();
+        assert_near(sunrise, day, 3, 43);
+        assert_near(sunset, day, 20, 21);
+
+        // Sydney on the same day, in the southern winter: sunrise at 21:00 UTC the day before and sunset at 06:53 UTC
+        let (sunrise, sunset) = sun_times(day, -33.8688, 151.2093).unwrap();
+        assert_near(sunrise, day.pred_opt().unwrap(), 21, 0);
+        assert_near(sunset, day, 6, 53);
+    }
+
+    #[test]
+    fn sun_times_are_none_during_polar_day_and_night() {
+        // Tromsø has midnight sun in June and polar night in December
+        let summer = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let winter = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        assert!(sun_times(summer, 69.6492, 18.9553).is_none());
+        assert!(sun_times(winter, 69.6492, 18.9553).is_none());
+    }
+
+    #[test]
+    fn brightness_blends_across_midnight() {
+        let mut curve = test_curve(&[("22:00", 0), ("02:00", 200), ("12:00", 200)]);
+        assert_eq!(curve.brightness_at(test_time(15, 22, 0)), 0);
+        assert_eq!(curve.brightness_at(test_time(15, 23, 0)), 50);
+        assert_eq!(curve.brightness_at(test_time(16, 0, 0)), 100);
+        assert_eq!(curve.brightness_at(test_time(16, 1, 0)), 150);
+        assert_eq!(curve.brightness_at(test_time(16, 2, 0)), 200);
+
+        // From the last point of the day back round to the first
+        assert_eq!(curve.brightness_at(test_time(16, 17, 0)), 100);
+    }
+
+    #[test]
+    fn coincident_points_step_between_brightnesses() {
+        let mut curve = test_curve(&[("06:00", 0), ("12:00", 100), ("12:00", 250)]);
+        assert_eq!(curve.brightness_at(test_time(15, 9, 0)), 50);
+        assert_eq!(curve.brightness_at(test_time(15, 12, 0)), 250);
+
+        // A single time of day gives the same brightness all day
+        let mut curve = test_curve(&[("12:00", 80), ("12:00", 80)]);
+        assert_eq!(curve.brightness_at(test_time(15, 3, 0)), 80);
+        assert_eq!(curve.brightness_at(test_time(15, 12, 0)), 80);
+
+        // A schedule without any points leaves the brightness alone
+        let mut curve = test_curve(&[]);
+        assert_eq!(curve.brightness_at(test_time(15, 12, 0)), u8::MAX);
+    }
+}
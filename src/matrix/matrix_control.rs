@@ -1,10 +1,16 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::matrix::matrix_brightness::BrightnessCurve;
 use crate::matrix::matrix_map::{MatrixMap, MatrixMapBuilder};
-use crate::matrix::matrix_state::MatrixState;
-use rs_ws281x::{ChannelBuilder, Controller, ControllerBuilder, StripType, WS2811Error};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use crate::matrix::matrix_state::{apply_changes, MatrixState, PixelChange};
+use crate::output::output_sink::new_output_sink;
+use chrono::Local;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How often to check the brightness schedule for a new brightness
+const BRIGHTNESS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Manages the matrix update thread
 pub(crate) struct MatrixController {
@@ -14,21 +20,15 @@ pub(crate) struct MatrixController {
     /// The current state of all LEDs in the matrix, as a two-dimensional array of BGRA color values
     matrix_state: Arc<Mutex<MatrixState>>,
 
-    /// The DMA channel to use while controlling the matrix
-    dma_channel: u16,
-
-    /// The GPIO pin to use while controlling the matrix
-    gpio_pin: u16,
-
-    /// The signal frequency to use while controlling the matrix
-    signal_frequency: u32,
-
-    /// The brightness of the matrix
-    brightness: u8,
+    /// The configuration used to create the matrix output
+    matrix_config: MatrixConfigurationArgs,
 
     /// Maps matrix pixels to LEDs on a strip
     matrix_map: MatrixMap,
 
+    /// The brightness that the update thread should render frames at
+    brightness: Arc<AtomicU8>,
+
     /// True if the matrix update thread is running
     matrix_update_thread_alive: Arc<AtomicBool>,
 
@@ -41,37 +41,23 @@ impl MatrixController {
     ///
     /// # Arguments
     ///
-    /// * `matrix_dimensions` - The size of the matrix in number of LEDs (width, height)
-    /// * `serpentine` - Whether or not the matrix is serpentine
-    /// * `vertical` - Whether or not the matrix is vertically wired
-    /// * `brighness` - The brightness of the matrix, from 0 to 255
-    /// * `gpio_pin` - The GPIO pin to use to control the matrix
-    /// * `dma_channel` - The DMA channel to use to control the matrix
-    /// * `signal_frequency` - The signal frequency to use to control the matrix
+    /// * `matrix_config` - The configuration of the matrix and its output
     ///
-    pub(crate) fn new(
-        matrix_dimensions: (usize, usize),
-        serpentine: bool,
-        mirror_horizontal: bool,
-        mirror_vertical: bool,
-        vertical: bool,
-        brightness: u8,
-        gpio_pin: u16,
-        dma_channel: u16,
-        signal_frequency: u32,
-    ) -> Self {
+    pub(crate) fn new(matrix_config: &MatrixConfigurationArgs) -> Self {
+        let matrix_dimensions = (matrix_config.width, matrix_config.height);
+
         // Create the matrix map
         let mut matrix_map = MatrixMapBuilder::new(matrix_dimensions.0, matrix_dimensions.1);
-        if serpentine {
+        if matrix_config.serpentine {
             matrix_map = matrix_map.serpentine();
         }
-        if vertical {
+        if matrix_config.vertical {
             matrix_map = matrix_map.vertical();
         }
-        if mirror_horizontal {
+        if matrix_config.mirror_horizontal {
             matrix_map = matrix_map.mirror_horizontally();
         }
-        if mirror_vertical {
+        if matrix_config.mirror_vertical {
             matrix_map = matrix_map.mirror_vertically();
         }
         let matrix_map = matrix_map.build();
@@ -82,17 +68,17 @@ impl MatrixController {
                 vec![[0; 4]; matrix_dimensions.0];
                 matrix_dimensions.1
             ])),
-            dma_channel,
-            gpio_pin,
-            signal_frequency,
-            brightness,
+            matrix_config: matrix_config.clone(),
             matrix_map,
+            brightness: Arc::new(AtomicU8::new(matrix_config.brightness)),
             matrix_update_thread_alive: Arc::new(AtomicBool::new(false)),
             matrix_update_thread_continue: Arc::new(AtomicBool::new(false)),
         }
     }
 
     /// Start the matrix controller
+    ///
+    /// Waits until the matrix update thread has created its output, so that the matrix can be updated as soon as this returns.
     pub(crate) fn start(&mut self) -> Result<(), ()> {
         if self.matrix_update_thread_alive.load(Ordering::Relaxed) {
             log::warn!("Matrix update thread already exists, ignoring this start command.");
@@ -107,43 +93,46 @@ impl MatrixController {
         let thread_matrix_state = Arc::clone(&self.matrix_state);
         let thread_continue = Arc::clone(&self.matrix_update_thread_continue);
         let thread_alive = Arc::clone(&self.matrix_update_thread_alive);
+        let thread_brightness = Arc::clone(&self.brightness);
         let width = self.matrix_dimensions.0;
         let height = self.matrix_dimensions.1;
-        let brightness = self.brightness;
-        let frequency = self.signal_frequency;
-        let dma_channel = self.dma_channel;
-        let gpio_pin = self.gpio_pin;
+        let matrix_config = self.matrix_config.clone();
         let matrix_map = self.matrix_map.clone();
+        let frame_time =
+            Duration::from_nanos((1_000_000_000.0 / self.matrix_config.fps).round() as u64);
 
         // Start the matrix update thread
+        let (started_sender, started_receiver) = mpsc::sync_channel::<Result<(), ()>>(1);
         thread::spawn(move || {
-            // Create the LED controller
-            let mut controller = match ControllerBuilder::new()
-                .freq(frequency)
-                .dma(dma_channel as i32)
-                .channel(
-                    0, // channel index
-                    ChannelBuilder::new()
-                        .pin(gpio_pin as i32)
-                        .count((width * height) as i32)
-                        .strip_type(StripType::Ws2812)
-                        .brightness(brightness)
-                        .build(),
-                )
-                .build()
-            {
-                Ok(controller) => controller,
-                Err(e) => {
-                    log::error!("Failed to create LED controller.");
-                    log::debug!("Failed with the following error: {e}");
+            // Create the output
+            let mut output = match new_output_sink(&matrix_config) {
+                Ok(output) => output,
+                Err(_) => {
+                    log::error!("Failed to create matrix output.");
+                    let _ = started_sender.send(Err(()));
                     return;
                 }
             };
 
             // Mark the thread as alive
             thread_alive.store(true, Ordering::Relaxed);
+            let _ = started_sender.send(Ok(()));
+
+            // Keep track of the brightness the output is set to, so that it is only changed when needed
+            let mut output_brightness = matrix_config.brightness;
+
+            // Dim the matrix through the day if there is a brightness schedule
+            let mut brightness_curve = matrix_config
+                .brightness_schedule
+                .as_ref()
+                .map(BrightnessCurve::new);
+            let mut curve_brightness = u8::MAX;
+            let mut last_curve_check: Option<Instant> = None;
 
             'update: loop {
+                // Mark the time when this frame started
+                let frame_start_time = Instant::now();
+
                 let current_state: MatrixState = match thread_matrix_state.lock() {
                     Ok(state) => state,
                     Err(_) => {
@@ -153,29 +142,37 @@ impl MatrixController {
                 }
                 .clone();
 
-                // Update the LEDs
-                {
-                    let leds = controller.leds_mut(0);
-                    for (y, row) in current_state.iter().enumerate() {
-                        for (x, color) in row.iter().enumerate() {
-                            leds[matrix_map.get(x, y)] = *color;
-                        }
+                // Reorder the matrix state into strip order, ignoring anything outside of the matrix
+                let mut leds = vec![[0; 4]; width * height];
+                for (y, row) in current_state.iter().take(height).enumerate() {
+                    for (x, color) in row.iter().take(width).enumerate() {
+                        leds[matrix_map.get(x, y)] = *color;
+                    }
+                }
+
+                // Follow the brightness schedule, if there is one
+                if let Some(brightness_curve) = &mut brightness_curve {
+                    if last_curve_check
+                        .is_none_or(|last| last.elapsed() >= BRIGHTNESS_CHECK_INTERVAL)
+                    {
+                        last_curve_check = Some(Instant::now());
+                        curve_brightness = brightness_curve.brightness_at(Local::now());
                     }
                 }
 
-                // Push the update to the LEDs
-                match controller.render() {
+                // Apply any change to the brightness before rendering
+                let brightness =
+                    scale_brightness(thread_brightness.load(Ordering::Relaxed), curve_brightness);
+                if brightness != output_brightness {
+                    output.set_brightness(brightness);
+                    output_brightness = brightness;
+                }
+
+                // Push the update to the output
+                match output.render(&leds) {
                     Ok(_) => { /* Do nothing */ }
-                    Err(e) => {
+                    Err(_) => {
                         log::error!("Failed to push plugin changes to matrix.");
-                        log::debug!("Failed with the following error: {e}");
-
-                        match e {
-                            WS2811Error::SpiTransfer => {
-                                log::warn!("Failed to transfer data to LEDs. It is possible that too few LEDs are connected, or the SPI buffer is too small.");
-                            }
-                            _ => {}
-                        }
                         break 'update;
                     }
                 }
@@ -185,22 +182,36 @@ impl MatrixController {
                     log::info!("Closing matrix update thread.");
                     break 'update;
                 }
+
+                // Wait until the next frame is due, so that outputs receive frames at the target framerate
+                if let Some(remaining_frame_time) =
+                    frame_time.checked_sub(frame_start_time.elapsed())
+                {
+                    thread::sleep(remaining_frame_time);
+                }
             }
 
             // If we are at this point, the thread is about to stop.
             // Let's clean up a bit by clearing the matrix
-            match clear_matrix(&mut controller) {
+            match output.clear() {
                 Ok(_) => { /* Do nothing */ }
                 Err(_) => {
                     log::warn!("Failed to clear matrix.")
                 }
             };
 
+            // Release the output before marking the thread as dead, so that it can finish up
+            drop(output);
+
             // Mark the thread as dead
             thread_alive.store(false, Ordering::Relaxed);
         });
 
-        Ok(())
+        // Wait for the output to be created, so that updates are not sent before the thread can take them
+        match started_receiver.recv() {
+            Ok(result) => result,
+            Err(_) => Err(()),
+        }
     }
 
     /// Stop the matrix update thread
@@ -245,18 +256,49 @@ impl MatrixController {
             }
         }
     }
+
+    /// Change the brightness of the matrix, from the next frame onwards
+    ///
+    /// # Arguments
+    ///
+    /// `brightness` - The new brightness, from 0 to 255
+    ///
+    pub(crate) fn set_brightness(&mut self, brightness: u8) {
+        self.brightness.store(brightness, Ordering::Relaxed);
+    }
+
+    /// Change a few pixels of the matrix, leaving the rest as they are
+    ///
+    /// # Arguments
+    ///
+    /// `changes` - The pixels to change
+    ///
+    pub(crate) fn apply_changes(&mut self, changes: &[PixelChange]) -> Result<(), ()> {
+        // Return an error if the thread is not alive
+        if !self.matrix_update_thread_alive.load(Ordering::Relaxed) {
+            return Err(());
+        }
+
+        match self.matrix_state.lock() {
+            Ok(mut matrix_state) => {
+                apply_changes(&mut matrix_state, changes);
+                Ok(())
+            }
+            Err(_) => {
+                log::error!("Failed to update matrix state.");
+                Err(())
+            }
+        }
+    }
 }
 
-/// Clear all LEDs in a rs_ws281x LED controller
+/// Dim a brightness by the brightness of the brightness schedule
 ///
 /// # Arguments
 ///
-/// `led_controller` - The LED controller to clear
+/// * `brightness` - The brightness of the matrix or plugin, from 0-255
+/// * `curve_brightness` - The brightness of the schedule, from 0-255
 ///
-pub(crate) fn clear_matrix(led_controller: &mut Controller) -> Result<(), WS2811Error> {
-    let leds = led_controller.leds_mut(0);
-    for led in leds {
-        *led = [0, 0, 0, 0];
-    }
-    led_controller.render()
+fn scale_brightness(brightness: u8, curve_brightness: u8) -> u8 {
+    ((brightness as u16 * curve_brightness as u16 + 127) / 255) as u8
 }
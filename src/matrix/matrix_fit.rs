@@ -0,0 +1,206 @@
+use crate::clargs::{FitPolicy, MatrixConfigurationArgs};
+use crate::matrix::matrix_state::MatrixState;
+use std::fmt;
+
+/// The shape of a frame returned by a plugin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FrameShape {
+    /// Number of rows in the frame
+    height: usize,
+
+    /// Length of the shortest row in the frame
+    min_width: usize,
+
+    /// Length of the longest row in the frame
+    max_width: usize,
+}
+
+impl FrameShape {
+    /// Get the shape of a frame
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to measure
+    ///
+    pub(crate) fn of(frame: &MatrixState) -> Self {
+        Self {
+            height: frame.len(),
+            min_width: frame.iter().map(|row| row.len()).min().unwrap_or(0),
+            max_width: frame.iter().map(|row| row.len()).max().unwrap_or(0),
+        }
+    }
+}
+
+impl fmt::Display for FrameShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min_width == self.max_width {
+            write!(f, "{}x{}", self.max_width, self.height)
+        } else {
+            write!(
+                f,
+                "ragged {}x{} (rows of {}-{} pixels)",
+                self.max_width, self.height, self.min_width, self.max_width
+            )
+        }
+    }
+}
+
+/// Makes plugin frames fit the matrix, according to a fit policy
+#[derive(Clone, Copy)]
+pub(crate) struct FrameFitter {
+    /// Width of the matrix, in number of LEDs
+    width: usize,
+
+    /// Height of the matrix, in number of LEDs
+    height: usize,
+
+    /// How to handle frames that do not match the matrix
+    policy: FitPolicy,
+}
+
+impl FrameFitter {
+    /// Create a new frame fitter
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to fit frames to
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Self {
+        Self {
+            width: config.width,
+            height: config.height,
+            policy: config.fit,
+        }
+    }
+
+    /// Get the fit policy
+    pub(crate) fn policy(&self) -> FitPolicy {
+        self.policy
+    }
+
+    /// Get the dimensions of the matrix, in number of LEDs (width, height)
+    pub(crate) fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Check if a frame of the given shape exactly matches the matrix
+    ///
+    /// # Arguments
+    ///
+    /// * `shape` - The shape of the frame
+    ///
+    pub(crate) fn matches(&self, shape: &FrameShape) -> bool {
+        shape.height == self.height
+            && shape.min_width == self.width
+            && shape.max_width == self.width
+    }
+
+    /// Make a frame fit the matrix
+    ///
+    /// Returns an error if the fit policy does not allow the frame to be used.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to fit
+    ///
+    pub(crate) fn fit(&self, frame: MatrixState) -> Result<MatrixState, ()> {
+        let shape = FrameShape::of(&frame);
+        if self.matches(&shape) {
+            return Ok(frame);
+        }
+
+        match self.policy {
+            FitPolicy::Reject => Err(()),
+            FitPolicy::Crop => Ok(self.crop(&frame)),
+            FitPolicy::Pad => {
+                if shape.max_width > self.width || shape.height > self.height {
+                    return Err(());
+                }
+                Ok(self.crop(&frame))
+            }
+            FitPolicy::Nearest => Ok(self.scale(&frame, &shape, false)),
+            FitPolicy::Bilinear => Ok(self.scale(&frame, &shape, true)),
+        }
+    }
+
+    /// Cut off pixels outside of the matrix, and fill in missing pixels with black
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to crop
+    ///
+    fn crop(&self, frame: &MatrixState) -> MatrixState {
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        frame
+                            .get(y)
+                            .and_then(|row| row.get(x))
+                            .copied()
+                            .unwrap_or([0; 4])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Scale a frame to the size of the matrix
+    ///
+    /// Short rows are treated as if they were filled in with black.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to scale
+    /// * `shape` - The shape of the frame
+    /// * `bilinear` - True to blend between neighboring pixels, false to use the nearest pixel
+    ///
+    fn scale(&self, frame: &MatrixState, shape: &FrameShape, bilinear: bool) -> MatrixState {
+        let source_width = shape.max_width;
+        let source_height = shape.height;
+        if source_width == 0 || source_height == 0 {
+            return vec![vec![[0; 4]; self.width]; self.height];
+        }
+
+        let pixel = |x: usize, y: usize| -> [u8; 4] { frame[y].get(x).copied().unwrap_or([0; 4]) };
+
+        // Map the center of a matrix pixel to a position in the frame
+        let x_ratio = source_width as f32 / self.width as f32;
+        let y_ratio = source_height as f32 / self.height as f32;
+
+        (0..self.height)
+            .map(|y| {
+                let source_y =
+                    ((y as f32 + 0.5) * y_ratio - 0.5).clamp(0.0, (source_height - 1) as f32);
+                (0..self.width)
+                    .map(|x| {
+                        let source_x = ((x as f32 + 0.5) * x_ratio - 0.5)
+                            .clamp(0.0, (source_width - 1) as f32);
+
+                        if !bilinear {
+                            return pixel(source_x.round() as usize, source_y.round() as usize);
+                        }
+
+                        // Blend the four pixels around the position
+                        let (x0, y0) = (source_x.floor() as usize, source_y.floor() as usize);
+                        let (x1, y1) = (
+                            (x0 + 1).min(source_width - 1),
+                            (y0 + 1).min(source_height - 1),
+                        );
+                        let (x_weight, y_weight) = (source_x - x0 as f32, source_y - y0 as f32);
+                        let (top_left, top_right) = (pixel(x0, y0), pixel(x1, y0));
+                        let (bottom_left, bottom_right) = (pixel(x0, y1), pixel(x1, y1));
+
+                        std::array::from_fn(|channel| {
+                            let top = top_left[channel] as f32 * (1.0 - x_weight)
+                                + top_right[channel] as f32 * x_weight;
+                            let bottom = bottom_left[channel] as f32 * (1.0 - x_weight)
+                                + bottom_right[channel] as f32 * x_weight;
+                            (top * (1.0 - y_weight) + bottom * y_weight).round() as u8
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -93,8 +93,8 @@ impl MatrixMapBuilder {
 
         // Mirror the matrix horizontally if needed
         if self.mirror_horizontal {
-            for y in 0..self.height {
-                map[y].reverse();
+            for row in map.iter_mut() {
+                row.reverse();
             }
         }
 
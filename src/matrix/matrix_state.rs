@@ -1 +1,43 @@
 pub type MatrixState = Vec<Vec<[u8; 4]>>;
+
+/// A new color for a single pixel of the matrix
+#[derive(Clone, Copy)]
+pub struct PixelChange {
+    /// X-coordinate of the pixel
+    pub x: usize,
+
+    /// Y-coordinate of the pixel
+    pub y: usize,
+
+    /// New color of the pixel, in the same format as the matrix state
+    pub color: [u8; 4],
+}
+
+/// An update to the matrix from a plugin
+pub enum MatrixUpdate {
+    /// A new state for the entire matrix
+    Full(MatrixState),
+
+    /// Changes to a few pixels, leaving the rest of the matrix as it was
+    Delta(Vec<PixelChange>),
+}
+
+/// Apply a set of pixel changes to a matrix state
+///
+/// Changes to pixels outside of the matrix are ignored.
+///
+/// # Arguments
+///
+/// * `matrix_state` - The matrix state to change
+/// * `changes` - The changes to apply
+///
+pub fn apply_changes(matrix_state: &mut MatrixState, changes: &[PixelChange]) {
+    for change in changes {
+        if let Some(pixel) = matrix_state
+            .get_mut(change.y)
+            .and_then(|row| row.get_mut(change.x))
+        {
+            *pixel = change.color;
+        }
+    }
+}
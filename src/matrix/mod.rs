@@ -1,3 +1,5 @@
+pub mod matrix_brightness;
 pub mod matrix_control;
-mod matrix_map;
+pub mod matrix_fit;
+pub mod matrix_map;
 pub mod matrix_state;
\ No newline at end of file
@@ -0,0 +1,10 @@
+mod output_artnet;
+mod output_ddp;
+mod output_fanout;
+mod output_opc;
+mod output_record;
+mod output_sacn;
+pub mod output_sink;
+mod output_terminal;
+mod output_threaded;
+mod output_ws281x;
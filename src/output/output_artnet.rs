@@ -0,0 +1,405 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::output::output_sink::{bind_udp_socket, to_rgb_bytes, OutputSink, PIXELS_PER_UNIVERSE};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+
+/// UDP port that Art-Net nodes listen on
+const ARTNET_PORT: u16 = 6454;
+
+/// Identifier that starts every Art-Net packet
+const ARTNET_ID: [u8; 8] = *b"Art-Net\0";
+
+/// Op code of an ArtDmx packet
+const ARTNET_OP_DMX: u16 = 0x5000;
+
+/// Op code of an ArtSync packet
+const ARTNET_OP_SYNC: u16 = 0x5200;
+
+/// Art-Net protocol version
+const ARTNET_PROTOCOL_VERSION: u16 = 14;
+
+/// Number of channels in a DMX universe
+const DMX_UNIVERSE_SIZE: usize = 512;
+
+/// Largest Art-Net port-address
+const ARTNET_MAX_PORT_ADDRESS: usize = 0x7fff;
+
+/// Largest Art-Net net
+const ARTNET_MAX_NET: u8 = 127;
+
+/// Largest Art-Net subnet, and largest universe within a subnet
+const ARTNET_MAX_SUBNET: u8 = 15;
+
+/// Largest channel offset that still leaves room for one RGB pixel in the first universe
+const ARTNET_MAX_CHANNEL_OFFSET: u16 = DMX_UNIVERSE_SIZE as u16 - 3;
+
+/// Sends frames to an Art-Net node as ArtDmx packets, followed by an ArtSync packet
+///
+/// The frame is split into universes starting from the configured port-address.
+/// The first universe starts after the channel offset, and every following universe holds 170 RGB pixels.
+/// Pixels are never split across universes.
+pub(crate) struct ArtNetOutput {
+    /// Socket to send packets from
+    socket: UdpSocket,
+
+    /// Address of the Art-Net node
+    node_address: SocketAddr,
+
+    /// Port-address (net, subnet and universe) of the first universe
+    start_port_address: u16,
+
+    /// Number of channels to skip at the start of the first universe
+    channel_offset: usize,
+
+    /// Sequence number of the next frame, from 1-255
+    sequence_number: u8,
+
+    /// Number of LEDs in the matrix
+    led_count: usize,
+
+    /// The brightness to apply to sent frames
+    brightness: u8,
+
+    /// True if the last attempt to send a frame failed
+    send_failing: bool,
+}
+
+impl ArtNetOutput {
+    /// Create a new Art-Net output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to set up the Art-Net sender with
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Result<Self, ()> {
+        // Check the values that the command line would have checked, as configuration files skip those checks
+        if config.artnet.artnet_net > ARTNET_MAX_NET {
+            log::error!(
+                "Art-Net net {} is out of range. Nets go from 0-{ARTNET_MAX_NET}.",
+                config.artnet.artnet_net
+            );
+            return Err(());
+        }
+        if config.artnet.artnet_subnet > ARTNET_MAX_SUBNET {
+            log::error!(
+                "Art-Net subnet {} is out of range. Subnets go from 0-{ARTNET_MAX_SUBNET}.",
+                config.artnet.artnet_subnet
+            );
+            return Err(());
+        }
+        if config.artnet.artnet_universe > ARTNET_MAX_SUBNET {
+            log::error!(
+                "Art-Net universe {} is out of range. Universes go from 0-{ARTNET_MAX_SUBNET}.",
+                config.artnet.artnet_universe
+            );
+            return Err(());
+        }
+        if config.artnet.artnet_channel_offset > ARTNET_MAX_CHANNEL_OFFSET {
+            log::error!(
+                "Art-Net channel offset {} is out of range. Offsets go from 0-{ARTNET_MAX_CHANNEL_OFFSET}.",
+                config.artnet.artnet_channel_offset
+            );
+            return Err(());
+        }
+
+        let led_count = config.width * config.height;
+        let channel_offset = config.artnet.artnet_channel_offset as usize;
+        let start_port_address = ((config.artnet.artnet_net as u16) << 8)
+            | ((config.artnet.artnet_subnet as u16) << 4)
+            | config.artnet.artnet_universe as u16;
+
+        // Make sure every universe has a valid port-address
+        let first_universe_pixels = (DMX_UNIVERSE_SIZE - channel_offset) / 3;
+        let universe_count = 1 + led_count
+            .saturating_sub(first_universe_pixels)
+            .div_ceil(PIXELS_PER_UNIVERSE);
+        if start_port_address as usize + universe_count - 1 > ARTNET_MAX_PORT_ADDRESS {
+            log::error!(
+                "The matrix needs {universe_count} universes, which do not fit after port-address {start_port_address}."
+            );
+            return Err(());
+        }
+
+        let node_ip = match config.artnet.artnet_node.parse::<IpAddr>() {
+            Ok(address) => address,
+            Err(_) => {
+                log::error!(
+                    "Unable to parse Art-Net node \"{}\" as an IP address.",
+                    config.artnet.artnet_node
+                );
+                return Err(());
+            }
+        };
+
+        let socket = match bind_udp_socket(node_ip) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("Failed to open a socket to send Art-Net packets from.");
+                log::debug!("Failed with the following error: {e:?}");
+                return Err(());
+            }
+        };
+
+        // Allow the node address to be a broadcast address
+        if let Err(e) = socket.set_broadcast(true) {
+            log::warn!("Unable to enable broadcast on the Art-Net socket.");
+            log::debug!("Failed with the following error: {e:?}");
+        }
+
+        log::info!(
+            "Sending Art-Net to {node_ip}, port-addresses {}-{}.",
+            start_port_address,
+            start_port_address as usize + universe_count - 1
+        );
+
+        Ok(Self {
+            socket,
+            node_address: SocketAddr::new(node_ip, ARTNET_PORT),
+            start_port_address,
+            channel_offset,
+            sequence_number: 1,
+            led_count,
+            brightness: config.brightness,
+            send_failing: false,
+        })
+    }
+
+    /// Build an ArtDmx packet
+    ///
+    /// # Arguments
+    ///
+    /// * `port_address` - The port-address of the universe the packet is for
+    /// * `channels` - The DMX channel data to send
+    ///
+    fn dmx_packet(&self, port_address: u16, channels: &[u8]) -> Vec<u8> {
+        // The data length must be even
+        let data_length = channels.len() + channels.len() % 2;
+
+        let mut packet = Vec::with_capacity(18 + data_length);
+        packet.extend_from_slice(&ARTNET_ID);
+        packet.extend_from_slice(&ARTNET_OP_DMX.to_le_bytes());
+        packet.extend_from_slice(&ARTNET_PROTOCOL_VERSION.to_be_bytes());
+        packet.push(self.sequence_number);
+        packet.push(0); // physical port
+        packet.extend_from_slice(&port_address.to_le_bytes()); // sub-uni, then net
+        packet.extend_from_slice(&(data_length as u16).to_be_bytes());
+        packet.extend_from_slice(channels);
+        packet.resize(18 + data_length, 0);
+
+        packet
+    }
+
+    /// Build an ArtSync packet
+    fn sync_packet() -> Vec<u8> {
+        let mut packet = Vec::with_capacity(14);
+        packet.extend_from_slice(&ARTNET_ID);
+        packet.extend_from_slice(&ARTNET_OP_SYNC.to_le_bytes());
+        packet.extend_from_slice(&ARTNET_PROTOCOL_VERSION.to_be_bytes());
+        packet.extend_from_slice(&[0, 0]); // aux
+        packet
+    }
+
+    /// Split a frame into the channel data of each universe
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    ///
+    fn universes(&self, leds: &[[u8; 4]]) -> Vec<Vec<u8>> {
+        let data = to_rgb_bytes(leds, self.brightness);
+
+        // The first universe starts after the channel offset, and only holds whole pixels
+        let first_universe_length =
+            ((DMX_UNIVERSE_SIZE - self.channel_offset) / 3 * 3).min(data.len());
+        let mut first_universe = vec![0; self.channel_offset];
+        first_universe.extend_from_slice(&data[..first_universe_length]);
+
+        let mut universes = vec![first_universe];
+        for channels in data[first_universe_length..].chunks(PIXELS_PER_UNIVERSE * 3) {
+            universes.push(channels.to_vec());
+        }
+        universes
+    }
+
+    /// Build the packets that send a frame to the node, followed by a sync, and move on the sequence number
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    ///
+    fn frame_packets(&mut self, leds: &[[u8; 4]]) -> Vec<Vec<u8>> {
+        let mut packets: Vec<Vec<u8>> = vec![];
+        for (universe_index, channels) in self.universes(leds).iter().enumerate() {
+            packets
+                .push(self.dmx_packet(self.start_port_address + universe_index as u16, channels));
+        }
+        packets.push(Self::sync_packet());
+
+        // Sequence numbers run from 1-255, since 0 disables sequencing
+        self.sequence_number = self.sequence_number % 255 + 1;
+
+        packets
+    }
+
+    /// Send a frame to the node, followed by a sync
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    ///
+    fn send(&mut self, leds: &[[u8; 4]]) {
+        let mut send_failed = false;
+        for packet in self.frame_packets(leds) {
+            if let Err(e) = self.socket.send_to(&packet, self.node_address) {
+                if !self.send_failing && !send_failed {
+                    log::warn!("Failed to send Art-Net packet to {}.", self.node_address);
+                    log::debug!("Failed with the following error: {e:?}");
+                }
+                send_failed = true;
+            }
+        }
+
+        if self.send_failing && !send_failed {
+            log::info!("Resumed sending Art-Net packets.");
+        }
+        self.send_failing = send_failed;
+    }
+}
+
+impl OutputSink for ArtNetOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        self.send(leds);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        let leds = vec![[0; 4]; self.led_count];
+        self.send(&leds);
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clargs::test_matrix_config;
+    use crate::output::output_sink::{test_leds, test_output};
+    use crate::receiver::receiver_artnet::ArtNetReceiver;
+    use crate::receiver::receiver_protocol::ReceiverProtocol;
+
+    #[test]
+    fn dmx_packets_have_correct_header_and_even_length() {
+        let mut output = test_output(
+            ArtNetOutput::new,
+            1,
+            1,
+            &[
+                "--artnet-node",
+                "127.0.0.1",
+                "--artnet-net",
+                "1",
+                "--artnet-subnet",
+                "2",
+                "--artnet-universe",
+                "3",
+            ],
+        );
+        let packets = output.frame_packets(&test_leds(1));
+        assert_eq!(packets.len(), 2);
+
+        let packet = &packets[0];
+        assert_eq!(packet[..8], ARTNET_ID);
+        assert_eq!(packet[8..10], ARTNET_OP_DMX.to_le_bytes());
+        assert_eq!(packet[10..12], ARTNET_PROTOCOL_VERSION.to_be_bytes());
+        assert_eq!(packet[12], 1);
+        assert_eq!(packet[14..16], [0x23, 0x01]);
+        assert_eq!(packet[16..18], 4u16.to_be_bytes());
+        assert_eq!(packet[18..], [0xaa, 0, 0, 0]);
+
+        assert_eq!(packets[1], ArtNetOutput::sync_packet());
+        assert_eq!(packets[1][8..10], ARTNET_OP_SYNC.to_le_bytes());
+    }
+
+    #[test]
+    fn sequence_numbers_skip_zero_when_they_wrap() {
+        let mut output = test_output(ArtNetOutput::new, 1, 1, &["--artnet-node", "127.0.0.1"]);
+        for expected in (1..=255u8).chain([1]) {
+            let packets = output.frame_packets(&test_leds(1));
+            assert_eq!(packets[0][12], expected);
+        }
+    }
+
+    #[test]
+    fn frame_split_across_universes_round_trips_through_the_receiver() {
+        // 200 pixels need two universes: 170 pixels in the first and 30 in the second
+        let mut output = test_output(
+            ArtNetOutput::new,
+            20,
+            10,
+            &["--artnet-node", "127.0.0.1", "--artnet-universe", "4"],
+        );
+        let leds = test_leds(200);
+        let packets = output.frame_packets(&leds);
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0][14..16], [4, 0]);
+        assert_eq!(packets[1][14..16], [5, 0]);
+        assert_eq!(packets[0].len(), 18 + 510);
+        assert_eq!(packets[1].len(), 18 + 90);
+
+        let mut receiver = ArtNetReceiver::new(Some(4), 200);
+        let mut frame = vec![0; 200 * 3];
+        assert!(!receiver.handle_packet(&packets[0], &mut frame));
+        assert!(receiver.handle_packet(&packets[1], &mut frame));
+        assert!(!receiver.handle_packet(&packets[2], &mut frame));
+        assert_eq!(frame, to_rgb_bytes(&leds, 255));
+    }
+
+    #[test]
+    fn channel_offset_shifts_the_first_universe_without_splitting_pixels() {
+        // An offset of 10 leaves room for 167 whole pixels in the first universe
+        let output = test_output(
+            ArtNetOutput::new,
+            20,
+            10,
+            &[
+                "--artnet-node",
+                "127.0.0.1",
+                "--artnet-channel-offset",
+                "10",
+            ],
+        );
+        let leds = test_leds(200);
+        let data = to_rgb_bytes(&leds, 255);
+        let universes = output.universes(&leds);
+
+        assert_eq!(universes.len(), 2);
+        assert_eq!(universes[0].len(), 10 + 167 * 3);
+        assert_eq!(universes[0][..10], [0; 10]);
+        assert_eq!(universes[0][10..], data[..167 * 3]);
+        assert_eq!(universes[1], data[167 * 3..]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_values_from_configuration_files() {
+        let config = test_matrix_config(&["-x", "2", "-y", "2"]);
+
+        let mut bad_config = config.clone();
+        bad_config.artnet.artnet_net = 128;
+        assert!(ArtNetOutput::new(&bad_config).is_err());
+
+        let mut bad_config = config.clone();
+        bad_config.artnet.artnet_subnet = 16;
+        assert!(ArtNetOutput::new(&bad_config).is_err());
+
+        let mut bad_config = config.clone();
+        bad_config.artnet.artnet_universe = 16;
+        assert!(ArtNetOutput::new(&bad_config).is_err());
+
+        let mut bad_config = config.clone();
+        bad_config.artnet.artnet_channel_offset = 600;
+        assert!(ArtNetOutput::new(&bad_config).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::output::output_sink::{bind_udp_socket, to_rgb_bytes, OutputSink};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// DDP header flag marking the protocol version
+const DDP_FLAG_VERSION_1: u8 = 0x40;
+
+/// DDP header flag telling the device to display the data it has received
+const DDP_FLAG_PUSH: u8 = 0x01;
+
+/// DDP data type for 8-bit RGB pixels
+const DDP_TYPE_RGB8: u8 = 0x0b;
+
+/// DDP destination ID of the device's default output
+const DDP_ID_DISPLAY: u8 = 0x01;
+
+/// Length of a DDP header, in bytes
+const DDP_HEADER_LENGTH: usize = 10;
+
+/// Largest amount of pixel data sent in a single DDP packet, in bytes (480 RGB pixels)
+const DDP_MAX_DATA_LENGTH: usize = 1440;
+
+/// Sends frames to a DDP device, such as a WLED controller, over UDP
+///
+/// Frames that do not fit in a single packet are split across several, with the push flag set on the last one.
+pub(crate) struct DdpOutput {
+    /// Socket to send packets from
+    socket: UdpSocket,
+
+    /// Address of the DDP device
+    address: SocketAddr,
+
+    /// Sequence number of the next frame, from 1-15
+    sequence_number: u8,
+
+    /// Number of LEDs in the matrix
+    led_count: usize,
+
+    /// The brightness to apply to sent frames
+    brightness: u8,
+
+    /// True if the last attempt to send a frame failed
+    send_failing: bool,
+}
+
+impl DdpOutput {
+    /// Create a new DDP output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to set up the DDP sender with
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Result<Self, ()> {
+        let address = match config
+            .ddp
+            .ddp_address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+        {
+            Some(address) => address,
+            None => {
+                log::error!(
+                    "Unable to resolve DDP device address \"{}\".",
+                    config.ddp.ddp_address
+                );
+                return Err(());
+            }
+        };
+
+        let socket = match bind_udp_socket(address.ip()) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("Failed to open a socket to send DDP packets from.");
+                log::debug!("Failed with the following error: {e:?}");
+                return Err(());
+            }
+        };
+
+        log::info!("Sending DDP to {address}.");
+
+        Ok(Self {
+            socket,
+            address,
+            sequence_number: 1,
+            led_count: config.width * config.height,
+            brightness: config.brightness,
+            send_failing: false,
+        })
+    }
+
+    /// Build the packets that send a frame to the device, and move on the sequence number
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    ///
+    fn frame_packets(&mut self, leds: &[[u8; 4]]) -> Vec<Vec<u8>> {
+        let data = to_rgb_bytes(leds, self.brightness);
+        let packet_count = data.chunks(DDP_MAX_DATA_LENGTH).len();
+        let mut packets = Vec::with_capacity(packet_count);
+
+        for (packet_index, chunk) in data.chunks(DDP_MAX_DATA_LENGTH).enumerate() {
+            // Only tell the device to display the frame once all of it has been sent
+            let mut flags = DDP_FLAG_VERSION_1;
+            if packet_index == packet_count - 1 {
+                flags |= DDP_FLAG_PUSH;
+            }
+
+            let data_offset = (packet_index * DDP_MAX_DATA_LENGTH) as u32;
+            let mut packet = Vec::with_capacity(DDP_HEADER_LENGTH + chunk.len());
+            packet.push(flags);
+            packet.push(self.sequence_number);
+            packet.push(DDP_TYPE_RGB8);
+            packet.push(DDP_ID_DISPLAY);
+            packet.extend_from_slice(&data_offset.to_be_bytes());
+            packet.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            packet.extend_from_slice(chunk);
+            packets.push(packet);
+        }
+
+        // Sequence numbers run from 1-15, since 0 disables sequencing
+        self.sequence_number = self.sequence_number % 15 + 1;
+
+        packets
+    }
+
+    /// Send a frame to the device
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    ///
+    fn send(&mut self, leds: &[[u8; 4]]) {
+        let mut send_failed = false;
+        for packet in self.frame_packets(leds) {
+            if let Err(e) = self.socket.send_to(&packet, self.address) {
+                if !self.send_failing && !send_failed {
+                    log::warn!("Failed to send DDP packet to {}.", self.address);
+                    log::debug!("Failed with the following error: {e:?}");
+                }
+                send_failed = true;
+            }
+        }
+
+        if self.send_failing && !send_failed {
+            log::info!("Resumed sending DDP packets.");
+        }
+        self.send_failing = send_failed;
+    }
+}
+
+impl OutputSink for DdpOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        self.send(leds);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        let leds = vec![[0; 4]; self.led_count];
+        self.send(&leds);
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::output_sink::{test_leds, test_output};
+    use crate::receiver::receiver_ddp::DdpReceiver;
+    use crate::receiver::receiver_protocol::ReceiverProtocol;
+    use std::time::Duration;
+
+    #[test]
+    fn frame_split_across_packets_round_trips_through_the_receiver() {
+        // 600 pixels need two packets: 480 pixels in the first and 120 in the second
+        let mut output = test_output(DdpOutput::new, 30, 20, &["--ddp-address", "127.0.0.1:4048"]);
+        let leds = test_leds(600);
+        let packets = output.frame_packets(&leds);
+        assert_eq!(packets.len(), 2);
+
+        // Only the last packet tells the device to display the frame
+        assert_eq!(
+            packets[0][..4],
+            [DDP_FLAG_VERSION_1, 1, DDP_TYPE_RGB8, DDP_ID_DISPLAY]
+        );
+        assert_eq!(packets[1][0], DDP_FLAG_VERSION_1 | DDP_FLAG_PUSH);
+        assert_eq!(packets[0][4..8], 0u32.to_be_bytes());
+        assert_eq!(packets[1][4..8], 1440u32.to_be_bytes());
+        assert_eq!(packets[0][8..10], 1440u16.to_be_bytes());
+        assert_eq!(packets[1][8..10], 360u16.to_be_bytes());
+
+        let mut receiver = DdpReceiver::new();
+        let mut frame = vec![0; 600 * 3];
+        assert!(!receiver.handle_packet(&packets[0], &mut frame));
+        assert!(receiver.handle_packet(&packets[1], &mut frame));
+        assert_eq!(frame, to_rgb_bytes(&leds, 255));
+    }
+
+    #[test]
+    fn sequence_numbers_skip_zero_when_they_wrap() {
+        let mut output = test_output(DdpOutput::new, 1, 1, &["--ddp-address", "127.0.0.1:4048"]);
+        for expected in (1..=15u8).chain([1]) {
+            let packets = output.frame_packets(&test_leds(1));
+            assert_eq!(packets[0][1], expected);
+        }
+    }
+
+    #[test]
+    fn sends_to_ipv4_and_ipv6_devices() {
+        for local_address in ["127.0.0.1:0", "[::1]:0"] {
+            let device = UdpSocket::bind(local_address).unwrap();
+            device
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut output = test_output(
+                DdpOutput::new,
+                1,
+                1,
+                &["--ddp-address", &device.local_addr().unwrap().to_string()],
+            );
+            output.render(&[[3, 2, 1, 255]]).unwrap();
+
+            let mut packet = [0; 64];
+            let length = device.recv(&mut packet).unwrap();
+            assert_eq!(packet[DDP_HEADER_LENGTH..length], [1, 2, 3]);
+        }
+    }
+}
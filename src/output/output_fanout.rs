@@ -0,0 +1,92 @@
+use crate::clargs::OutputKind;
+use crate::output::output_sink::OutputSink;
+
+/// An output that has been started as part of a fan-out
+struct FanOutTarget {
+    /// The kind of output, for logging
+    kind: OutputKind,
+
+    /// The output itself
+    output: Box<dyn OutputSink>,
+
+    /// True if the last frame failed to render to this output
+    failing: bool,
+}
+
+/// Renders every frame to several outputs at once
+///
+/// A failing output does not stop frames from reaching the others.
+/// Rendering only fails once every output is failing.
+pub(crate) struct FanOutOutput {
+    /// The outputs to render frames to
+    targets: Vec<FanOutTarget>,
+}
+
+impl FanOutOutput {
+    /// Create a new fan-out output
+    ///
+    /// # Arguments
+    ///
+    /// * `outputs` - The outputs to render frames to, along with their kind
+    ///
+    pub(crate) fn new(outputs: Vec<(OutputKind, Box<dyn OutputSink>)>) -> Self {
+        let kinds: Vec<OutputKind> = outputs.iter().map(|(kind, _)| *kind).collect();
+        log::info!("Rendering frames to outputs {kinds:?}.");
+
+        Self {
+            targets: outputs
+                .into_iter()
+                .map(|(kind, output)| FanOutTarget {
+                    kind,
+                    output,
+                    failing: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// Run an operation on every output, keeping track of which outputs are failing
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation to run on each output
+    ///
+    fn for_each_target(
+        &mut self,
+        mut operation: impl FnMut(&mut dyn OutputSink) -> Result<(), ()>,
+    ) -> Result<(), ()> {
+        for target in self.targets.iter_mut() {
+            let failed = operation(target.output.as_mut()).is_err();
+            if failed && !target.failing {
+                log::warn!(
+                    "The {:?} output is failing. Frames will still be sent to the other outputs.",
+                    target.kind
+                );
+            } else if !failed && target.failing {
+                log::info!("The {:?} output has recovered.", target.kind);
+            }
+            target.failing = failed;
+        }
+
+        if self.targets.iter().all(|target| target.failing) {
+            return Err(());
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for FanOutOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        self.for_each_target(|output| output.render(leds))
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        self.for_each_target(|output| output.clear())
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        for target in self.targets.iter_mut() {
+            target.output.set_brightness(brightness);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::output::output_sink::{to_rgb_bytes, OutputSink};
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// OPC command to set the colors of a channel's pixels
+const OPC_SET_PIXEL_COLORS: u8 = 0;
+
+/// Largest number of pixels that fit in a single OPC message
+const OPC_MAX_PIXELS: usize = u16::MAX as usize / 3;
+
+/// Time to wait while connecting or sending to an OPC server
+const OPC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Time to wait between attempts to reconnect to an OPC server
+const OPC_RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sends frames to an Open Pixel Control server over TCP
+///
+/// Connections are made on a background thread, so that an unreachable server never holds up the matrix update thread.
+/// While there is no connection, frames are dropped and the connection is retried periodically.
+pub(crate) struct OpcOutput {
+    /// Address of the OPC server
+    address: SocketAddr,
+
+    /// OPC channel to send frames to
+    channel: u8,
+
+    /// Number of LEDs in the matrix
+    led_count: usize,
+
+    /// The brightness to apply to sent frames
+    brightness: u8,
+
+    /// Connection to the OPC server, if one is open
+    stream: Option<TcpStream>,
+
+    /// Receives the result of the connection attempt that is in progress, if there is one
+    pending_connection: Option<Receiver<io::Result<TcpStream>>>,
+
+    /// The time of the last attempt to connect to the OPC server
+    last_connect_attempt: Option<Instant>,
+}
+
+impl OpcOutput {
+    /// Create a new OPC output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to set up the OPC client with
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Result<Self, ()> {
+        let led_count = config.width * config.height;
+        if led_count > OPC_MAX_PIXELS {
+            log::error!("The matrix has too many LEDs to be sent in a single OPC message (maximum of {OPC_MAX_PIXELS}).");
+            return Err(());
+        }
+
+        let address = match config
+            .opc
+            .opc_address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+        {
+            Some(address) => address,
+            None => {
+                log::error!(
+                    "Unable to resolve OPC server address \"{}\".",
+                    config.opc.opc_address
+                );
+                return Err(());
+            }
+        };
+
+        let mut output = Self {
+            address,
+            channel: config.opc.opc_channel,
+            led_count,
+            brightness: config.brightness,
+            stream: None,
+            pending_connection: None,
+            last_connect_attempt: None,
+        };
+        output.connect();
+
+        Ok(output)
+    }
+
+    /// Start connecting to the OPC server in the background, if enough time has passed since the last attempt
+    fn connect(&mut self) {
+        if self.pending_connection.is_some() {
+            return;
+        }
+        if let Some(last_connect_attempt) = self.last_connect_attempt {
+            if last_connect_attempt.elapsed() < OPC_RECONNECT_INTERVAL {
+                return;
+            }
+        }
+        self.last_connect_attempt = Some(Instant::now());
+
+        let (sender, receiver) = mpsc::channel();
+        let address = self.address;
+        thread::spawn(move || {
+            // Nobody is waiting for the result if the output has been dropped
+            let _ = sender.send(TcpStream::connect_timeout(&address, OPC_TIMEOUT));
+        });
+        self.pending_connection = Some(receiver);
+    }
+
+    /// Pick up the result of the connection attempt in progress, if it has finished
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - Time to wait for the attempt to finish
+    ///
+    fn finish_connecting(&mut self, wait: Duration) {
+        let receiver = match &self.pending_connection {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        let result = if wait.is_zero() {
+            match receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.pending_connection = None;
+                    return;
+                }
+            }
+        } else {
+            match receiver.recv_timeout(wait) {
+                Ok(result) => result,
+                Err(RecvTimeoutError::Timeout) => return,
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.pending_connection = None;
+                    return;
+                }
+            }
+        };
+        self.pending_connection = None;
+
+        match result {
+            Ok(stream) => {
+                let _ = stream.set_nodelay(true);
+                let _ = stream.set_write_timeout(Some(OPC_TIMEOUT));
+                log::info!("Connected to OPC server at {}.", self.address);
+                self.stream = Some(stream);
+            }
+            Err(e) => {
+                log::warn!(
+                    "Unable to connect to OPC server at {}. Frames will be dropped until a connection can be made.",
+                    self.address
+                );
+                log::debug!("Failed with the following error: {e:?}");
+            }
+        }
+    }
+
+    /// Send a set pixel colors message to the OPC server, dropping it if there is no connection
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    /// * `wait` - Time to wait for a connection attempt in progress to finish
+    ///
+    fn send(&mut self, leds: &[[u8; 4]], wait: Duration) {
+        if self.stream.is_none() {
+            self.connect();
+            self.finish_connecting(wait);
+        }
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        let data = to_rgb_bytes(leds, self.brightness);
+        let mut message = Vec::with_capacity(4 + data.len());
+        message.push(self.channel);
+        message.push(OPC_SET_PIXEL_COLORS);
+        message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        message.extend_from_slice(&data);
+
+        if let Err(e) = stream.write_all(&message) {
+            log::warn!("Lost connection to OPC server at {}.", self.address);
+            log::debug!("Failed with the following error: {e:?}");
+            self.stream = None;
+        }
+    }
+}
+
+impl OutputSink for OpcOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        self.send(leds, Duration::ZERO);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        // Give a connection started just before clearing a chance to finish, so that the clear is not dropped
+        let leds = vec![[0; 4]; self.led_count];
+        self.send(&leds, OPC_TIMEOUT);
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clargs::test_matrix_config;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// Longest time to wait for the output to do something before failing a test
+    const TEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// Render frames until the output connects to the listener
+    fn accept_while_rendering(
+        listener: &TcpListener,
+        output: &mut OpcOutput,
+        leds: &[[u8; 4]],
+    ) -> TcpStream {
+        listener.set_nonblocking(true).unwrap();
+        let start_time = Instant::now();
+        loop {
+            output.render(leds).unwrap();
+            match listener.accept() {
+                Ok((socket, _)) => {
+                    socket.set_nonblocking(false).unwrap();
+                    return socket;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    assert!(
+                        start_time.elapsed() < TEST_TIMEOUT,
+                        "the output never connected"
+                    );
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => panic!("failed to accept connection: {e:?}"),
+            }
+        }
+    }
+
+    /// Render frames until a whole message arrives at the socket, and read it
+    fn read_message_while_rendering(
+        socket: &mut TcpStream,
+        output: &mut OpcOutput,
+        leds: &[[u8; 4]],
+    ) -> ([u8; 4], Vec<u8>) {
+        socket
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        let start_time = Instant::now();
+        let mut header = [0; 4];
+        while socket.peek(&mut header).unwrap_or(0) < header.len() {
+            assert!(start_time.elapsed() < TEST_TIMEOUT, "no message arrived");
+            output.render(leds).unwrap();
+        }
+
+        socket.set_read_timeout(Some(TEST_TIMEOUT)).unwrap();
+        socket.read_exact(&mut header).unwrap();
+        let mut payload = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize];
+        socket.read_exact(&mut payload).unwrap();
+        (header, payload)
+    }
+
+    #[test]
+    fn sends_header_and_rgb_payload() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let config = test_matrix_config(&[
+            "-x",
+            "2",
+            "-y",
+            "1",
+            "--opc-address",
+            &address,
+            "--opc-channel",
+            "3",
+        ]);
+        let mut output = OpcOutput::new(&config).unwrap();
+
+        // LEDs are stored as BGRA, and sent as RGB
+        let leds = [[1, 2, 3, 255], [4, 5, 6, 255]];
+        let mut socket = accept_while_rendering(&listener, &mut output, &leds);
+        let (header, payload) = read_message_while_rendering(&mut socket, &mut output, &leds);
+
+        assert_eq!(header, [3, OPC_SET_PIXEL_COLORS, 0, 6]);
+        assert_eq!(payload, [3, 2, 1, 6, 5, 4]);
+    }
+
+    #[test]
+    fn reconnects_after_the_server_drops_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let config = test_matrix_config(&["-x", "1", "-y", "1", "--opc-address", &address]);
+        let mut output = OpcOutput::new(&config).unwrap();
+
+        let leds = [[0, 0, 255, 255]];
+        let mut socket = accept_while_rendering(&listener, &mut output, &leds);
+        read_message_while_rendering(&mut socket, &mut output, &leds);
+        drop(socket);
+
+        // The output notices the lost connection on a later write, and connects again after the reconnect interval
+        let mut socket = accept_while_rendering(&listener, &mut output, &leds);
+        let (header, payload) = read_message_while_rendering(&mut socket, &mut output, &leds);
+        assert_eq!(header, [0, OPC_SET_PIXEL_COLORS, 0, 3]);
+        assert_eq!(payload, [255, 0, 0]);
+    }
+
+    #[test]
+    fn rendering_does_not_wait_for_an_unreachable_server() {
+        // Nothing listens on this address, and connecting to a non-routable address takes until the timeout
+        let config =
+            test_matrix_config(&["-x", "1", "-y", "1", "--opc-address", "10.255.255.1:7890"]);
+        let mut output = OpcOutput::new(&config).unwrap();
+
+        let start_time = Instant::now();
+        for _ in 0..10 {
+            output.render(&[[0; 4]]).unwrap();
+        }
+        assert!(start_time.elapsed() < OPC_TIMEOUT);
+    }
+}
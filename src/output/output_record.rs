@@ -0,0 +1,234 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::output::output_sink::{bgra_to_rgba, scale_brightness, OutputSink};
+use serde::Serialize;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Name of the file that raw frames are written to, inside the recording directory
+const RECORD_FRAMES_FILE_NAME: &str = "frames.rgba";
+
+/// Name of the file that describes the recording, inside the recording directory
+const RECORD_INFO_FILE_NAME: &str = "frames.json";
+
+/// Name of the file that the frame index is written to, one line per frame, inside the recording directory
+const RECORD_INDEX_FILE_NAME: &str = "frames.jsonl";
+
+/// Describes the contents of a recording
+#[derive(Serialize)]
+struct RecordInfo {
+    /// Width of the matrix, in number of LEDs
+    width: usize,
+
+    /// Height of the matrix, in number of LEDs
+    height: usize,
+
+    /// Layout of the bytes of a single LED in the frames file
+    pixel_format: String,
+}
+
+/// Describes a single frame in a recording
+#[derive(Serialize)]
+struct RecordedFrame {
+    /// Byte offset of the frame in the frames file
+    offset: u64,
+
+    /// Time since the recording started, in milliseconds
+    timestamp_ms: u64,
+}
+
+/// The files that a recording is written to
+struct RecordFiles {
+    /// Writer for the raw frames file
+    frame_writer: BufWriter<File>,
+
+    /// Writer for the frame index file
+    index_writer: BufWriter<File>,
+}
+
+/// Records every rendered frame to a directory, instead of driving any LEDs
+///
+/// Frames are written in strip order, after brightness has been applied, as a stream of raw RGBA bytes.
+/// Each frame is added to the frame index as soon as it is written, so that the recording survives Matricks being killed.
+/// The recording files are only created once the first frame is rendered, so that clearing the matrix leaves an earlier recording alone.
+pub(crate) struct RecordOutput {
+    /// Directory to write the recording to
+    record_path: PathBuf,
+
+    /// The open recording files, once the first frame has been rendered
+    files: Option<RecordFiles>,
+
+    /// Description of the recording
+    info: RecordInfo,
+
+    /// Number of frames recorded so far
+    frame_count: u64,
+
+    /// Number of bytes written to the frames file so far
+    bytes_written: u64,
+
+    /// The brightness to apply to recorded frames
+    brightness: u8,
+
+    /// The time at which the recording started
+    start_time: Instant,
+}
+
+impl RecordOutput {
+    /// Create a new recording output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to set up the recording with
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Result<Self, ()> {
+        let record_path = PathBuf::from(&config.record.record_path);
+
+        // Make sure the recording directory exists
+        if let Err(e) = fs::create_dir_all(&record_path) {
+            log::error!(
+                "Failed to create recording directory at path \"{}\".",
+                config.record.record_path
+            );
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+
+        Ok(Self {
+            record_path,
+            files: None,
+            info: RecordInfo {
+                width: config.width,
+                height: config.height,
+                pixel_format: String::from("rgba8"),
+            },
+            frame_count: 0,
+            bytes_written: 0,
+            brightness: config.brightness,
+            start_time: Instant::now(),
+        })
+    }
+
+    /// Start a new recording, replacing any recording already in the directory
+    fn open_files(&self) -> Result<RecordFiles, ()> {
+        log::info!(
+            "Recording frames to directory \"{}\".",
+            self.record_path.display()
+        );
+
+        // Describe the recording
+        let info_path = self.record_path.join(RECORD_INFO_FILE_NAME);
+        let info_string = match serde_json::to_string_pretty(&self.info) {
+            Ok(string) => string,
+            Err(e) => {
+                log::error!("Failed to serialize recording description.");
+                log::debug!("Failed with the following error: {e:?}");
+                return Err(());
+            }
+        };
+        if let Err(e) = fs::write(&info_path, info_string) {
+            log::error!(
+                "Failed to write recording description to path \"{}\".",
+                info_path.display()
+            );
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+
+        Ok(RecordFiles {
+            frame_writer: create_record_file(&self.record_path.join(RECORD_FRAMES_FILE_NAME))?,
+            index_writer: create_record_file(&self.record_path.join(RECORD_INDEX_FILE_NAME))?,
+        })
+    }
+}
+
+/// Create one of the files of a recording
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to create
+///
+fn create_record_file(path: &Path) -> Result<BufWriter<File>, ()> {
+    match File::create(path) {
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(e) => {
+            log::error!(
+                "Failed to create recording file at path \"{}\".",
+                path.display()
+            );
+            log::debug!("Failed with the following error: {e:?}");
+            Err(())
+        }
+    }
+}
+
+impl OutputSink for RecordOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        // Start the recording with the first frame
+        if self.files.is_none() {
+            self.files = Some(self.open_files()?);
+            self.start_time = Instant::now();
+        }
+        let Some(files) = &mut self.files else {
+            return Err(());
+        };
+
+        let frame: Vec<u8> = leds
+            .iter()
+            .flat_map(|color| bgra_to_rgba(scale_brightness(*color, self.brightness)))
+            .collect();
+        let recorded_frame = RecordedFrame {
+            offset: self.bytes_written,
+            timestamp_ms: self.start_time.elapsed().as_millis() as u64,
+        };
+        let index_line = match serde_json::to_string(&recorded_frame) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to serialize recording index entry.");
+                log::debug!("Failed with the following error: {e:?}");
+                return Err(());
+            }
+        };
+
+        // Write the frame before its index line, so that the index never points past the end of the frames file
+        let write_result = files
+            .frame_writer
+            .write_all(&frame)
+            .and_then(|_| files.frame_writer.flush())
+            .and_then(|_| writeln!(files.index_writer, "{index_line}"))
+            .and_then(|_| files.index_writer.flush());
+        if let Err(e) = write_result {
+            log::error!("Failed to write frame to recording.");
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+
+        self.frame_count += 1;
+        self.bytes_written += frame.len() as u64;
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        // There are no LEDs to turn off, and recording a blank frame would start a new recording when clearing the matrix
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+}
+
+impl Drop for RecordOutput {
+    fn drop(&mut self) {
+        if self.files.is_some() {
+            log::info!(
+                "Recorded {} frames to \"{}\".",
+                self.frame_count,
+                self.record_path.display()
+            );
+        }
+    }
+}
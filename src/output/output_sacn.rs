@@ -0,0 +1,449 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::output::output_sink::{bind_udp_socket, to_rgb_bytes, OutputSink, PIXELS_PER_UNIVERSE};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use uuid::Uuid;
+
+/// UDP port that E1.31 receivers listen on
+const SACN_PORT: u16 = 5568;
+
+/// ACN packet identifier that starts the root layer of every E1.31 packet
+const SACN_PACKET_IDENTIFIER: [u8; 12] = *b"ASC-E1.17\0\0\0";
+
+/// Root layer vector for E1.31 data packets
+const SACN_VECTOR_ROOT_DATA: u32 = 0x0000_0004;
+
+/// Framing layer vector for E1.31 data packets
+const SACN_VECTOR_FRAMING_DATA: u32 = 0x0000_0002;
+
+/// DMP layer vector to set properties
+const SACN_VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+/// Framing layer option bit marking the end of a stream
+const SACN_OPTION_STREAM_TERMINATED: u8 = 0x40;
+
+/// Number of times to send a stream termination packet, as recommended by the standard
+const SACN_TERMINATION_PACKET_COUNT: usize = 3;
+
+/// Lowest universe that E1.31 data can be sent to
+const SACN_MIN_UNIVERSE: u16 = 1;
+
+/// Highest universe that E1.31 data can be sent to
+const SACN_MAX_UNIVERSE: u16 = 63999;
+
+/// Highest priority of an E1.31 stream
+const SACN_MAX_PRIORITY: u8 = 200;
+
+/// Largest number of bytes in a source name, including the null terminator
+const SACN_SOURCE_NAME_LENGTH: usize = 64;
+
+/// Sends frames to E1.31 (sACN) receivers over UDP
+///
+/// The frame is split into universes of 170 RGB pixels each, starting from the configured universe.
+pub(crate) struct SacnOutput {
+    /// Socket to send packets from
+    socket: UdpSocket,
+
+    /// Unicast address to send packets to, or `None` to multicast each universe
+    destination: Option<IpAddr>,
+
+    /// Universe that the first pixels of the frame are sent to
+    start_universe: u16,
+
+    /// Priority of the stream
+    priority: u8,
+
+    /// Null padded source name of the stream
+    source_name: [u8; SACN_SOURCE_NAME_LENGTH],
+
+    /// Component identifier of this source
+    cid: Uuid,
+
+    /// Sequence number of the next packet in each universe
+    sequence_numbers: Vec<u8>,
+
+    /// Number of LEDs in the matrix
+    led_count: usize,
+
+    /// The brightness to apply to sent frames
+    brightness: u8,
+
+    /// True if the last attempt to send a frame failed
+    send_failing: bool,
+}
+
+impl SacnOutput {
+    /// Create a new sACN output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to set up the sACN sender with
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Result<Self, ()> {
+        // Check the values that the command line would have checked, as configuration files skip those checks
+        if !(SACN_MIN_UNIVERSE..=SACN_MAX_UNIVERSE).contains(&config.sacn.sacn_universe) {
+            log::error!(
+                "sACN universe {} is out of range. Universes go from {SACN_MIN_UNIVERSE}-{SACN_MAX_UNIVERSE}.",
+                config.sacn.sacn_universe
+            );
+            return Err(());
+        }
+        if config.sacn.sacn_priority > SACN_MAX_PRIORITY {
+            log::error!(
+                "sACN priority {} is out of range. Priorities go from 0-{SACN_MAX_PRIORITY}.",
+                config.sacn.sacn_priority
+            );
+            return Err(());
+        }
+
+        let led_count = config.width * config.height;
+        if led_count == 0 {
+            log::error!("The matrix has no LEDs to send over sACN.");
+            return Err(());
+        }
+        let universe_count = led_count.div_ceil(PIXELS_PER_UNIVERSE);
+        if config.sacn.sacn_universe as usize + universe_count - 1 > SACN_MAX_UNIVERSE as usize {
+            log::error!(
+                "The matrix needs {universe_count} universes, which do not fit after starting universe {}.",
+                config.sacn.sacn_universe
+            );
+            return Err(());
+        }
+
+        let destination = match &config.sacn.sacn_destination {
+            None => None,
+            Some(destination) => match destination.parse::<IpAddr>() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    log::error!(
+                        "Unable to parse sACN destination \"{destination}\" as an IP address."
+                    );
+                    return Err(());
+                }
+            },
+        };
+
+        // Universes are multicast over IPv4 unless a destination is given
+        let target = destination.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let socket = match bind_udp_socket(target) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("Failed to open a socket to send sACN packets from.");
+                log::debug!("Failed with the following error: {e:?}");
+                return Err(());
+            }
+        };
+
+        // Fit the source name in the packet, leaving room for a null terminator
+        let mut source_name = [0; SACN_SOURCE_NAME_LENGTH];
+        let name_bytes = config.sacn.sacn_source_name.as_bytes();
+        let name_length = name_bytes.len().min(SACN_SOURCE_NAME_LENGTH - 1);
+        source_name[..name_length].copy_from_slice(&name_bytes[..name_length]);
+
+        log::info!(
+            "Sending sACN to universes {}-{}.",
+            config.sacn.sacn_universe,
+            config.sacn.sacn_universe as usize + universe_count - 1
+        );
+
+        Ok(Self {
+            socket,
+            destination,
+            start_universe: config.sacn.sacn_universe,
+            priority: config.sacn.sacn_priority,
+            source_name,
+            cid: Uuid::new_v4(),
+            sequence_numbers: vec![0; universe_count],
+            led_count,
+            brightness: config.brightness,
+            send_failing: false,
+        })
+    }
+
+    /// Build an E1.31 data packet
+    ///
+    /// # Arguments
+    ///
+    /// * `universe` - The universe the packet is for
+    /// * `sequence_number` - The sequence number of the packet in its universe
+    /// * `options` - The framing layer option bits
+    /// * `slots` - The DMX slot data to send, not including the start code
+    ///
+    fn data_packet(
+        &self,
+        universe: u16,
+        sequence_number: u8,
+        options: u8,
+        slots: &[u8],
+    ) -> Vec<u8> {
+        let packet_length = 126 + slots.len();
+        let mut packet = Vec::with_capacity(packet_length);
+
+        // Root layer
+        packet.extend_from_slice(&0x0010u16.to_be_bytes()); // preamble size
+        packet.extend_from_slice(&0x0000u16.to_be_bytes()); // postamble size
+        packet.extend_from_slice(&SACN_PACKET_IDENTIFIER);
+        packet.extend_from_slice(&flags_and_length(packet_length - 16));
+        packet.extend_from_slice(&SACN_VECTOR_ROOT_DATA.to_be_bytes());
+        packet.extend_from_slice(self.cid.as_bytes());
+
+        // Framing layer
+        packet.extend_from_slice(&flags_and_length(packet_length - 38));
+        packet.extend_from_slice(&SACN_VECTOR_FRAMING_DATA.to_be_bytes());
+        packet.extend_from_slice(&self.source_name);
+        packet.push(self.priority);
+        packet.extend_from_slice(&0u16.to_be_bytes()); // synchronization address
+        packet.push(sequence_number);
+        packet.push(options);
+        packet.extend_from_slice(&universe.to_be_bytes());
+
+        // DMP layer
+        packet.extend_from_slice(&flags_and_length(packet_length - 115));
+        packet.push(SACN_VECTOR_DMP_SET_PROPERTY);
+        packet.push(0xa1); // address type and data type
+        packet.extend_from_slice(&0u16.to_be_bytes()); // first property address
+        packet.extend_from_slice(&1u16.to_be_bytes()); // address increment
+        packet.extend_from_slice(&(slots.len() as u16 + 1).to_be_bytes()); // property value count
+        packet.push(0x00); // DMX start code
+        packet.extend_from_slice(slots);
+
+        packet
+    }
+
+    /// Get the address to send a universe's packets to
+    fn universe_address(&self, universe: u16) -> SocketAddr {
+        match self.destination {
+            Some(address) => SocketAddr::new(address, SACN_PORT),
+            None => {
+                let [high, low] = universe.to_be_bytes();
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, high, low)), SACN_PORT)
+            }
+        }
+    }
+
+    /// Build the packets that send a frame to every universe, moving on the sequence number of each universe
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    /// * `options` - The framing layer option bits to send with each packet
+    ///
+    fn frame_packets(&mut self, leds: &[[u8; 4]], options: u8) -> Vec<(u16, Vec<u8>)> {
+        let data = to_rgb_bytes(leds, self.brightness);
+        data.chunks(PIXELS_PER_UNIVERSE * 3)
+            .enumerate()
+            .map(|(universe_index, slots)| {
+                let universe = self.start_universe + universe_index as u16;
+                let sequence_number = self.sequence_numbers[universe_index];
+                self.sequence_numbers[universe_index] = sequence_number.wrapping_add(1);
+                (
+                    universe,
+                    self.data_packet(universe, sequence_number, options, slots),
+                )
+            })
+            .collect()
+    }
+
+    /// Send a frame to every universe
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    /// * `options` - The framing layer option bits to send with each packet
+    ///
+    fn send(&mut self, leds: &[[u8; 4]], options: u8) {
+        let mut send_failed = false;
+
+        for (universe, packet) in self.frame_packets(leds, options) {
+            if let Err(e) = self
+                .socket
+                .send_to(&packet, self.universe_address(universe))
+            {
+                if !self.send_failing {
+                    log::warn!("Failed to send sACN packet for universe {universe}.");
+                    log::debug!("Failed with the following error: {e:?}");
+                }
+                send_failed = true;
+            }
+        }
+
+        if self.send_failing && !send_failed {
+            log::info!("Resumed sending sACN packets.");
+        }
+        self.send_failing = send_failed;
+    }
+}
+
+impl OutputSink for SacnOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        self.send(leds, 0);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        let leds = vec![[0; 4]; self.led_count];
+        self.send(&leds, 0);
+
+        // Let receivers know that the stream has ended
+        for _ in 0..SACN_TERMINATION_PACKET_COUNT {
+            self.send(&leds, SACN_OPTION_STREAM_TERMINATED);
+        }
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+}
+
+/// Encode the flags and length field that starts each layer of an E1.31 packet
+///
+/// # Arguments
+///
+/// * `length` - Length of the layer, in bytes
+///
+fn flags_and_length(length: usize) -> [u8; 2] {
+    (0x7000 | length as u16).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clargs::test_matrix_config;
+    use crate::output::output_sink::{test_leds, test_output};
+    use crate::receiver::receiver_protocol::ReceiverProtocol;
+    use crate::receiver::receiver_sacn::SacnReceiver;
+
+    #[test]
+    fn layers_have_correct_flags_and_lengths() {
+        let mut output = test_output(
+            SacnOutput::new,
+            2,
+            2,
+            &[
+                "--sacn-destination",
+                "127.0.0.1",
+                "--sacn-universe",
+                "7",
+                "--sacn-priority",
+                "150",
+            ],
+        );
+        let packets = output.frame_packets(&test_leds(4), 0);
+        assert_eq!(packets.len(), 1);
+
+        let (universe, packet) = &packets[0];
+        assert_eq!(*universe, 7);
+        assert_eq!(packet.len(), 126 + 12);
+        assert_eq!(
+            packet[16..18],
+            (0x7000 | (packet.len() - 16) as u16).to_be_bytes()
+        );
+        assert_eq!(
+            packet[38..40],
+            (0x7000 | (packet.len() - 38) as u16).to_be_bytes()
+        );
+        assert_eq!(
+            packet[115..117],
+            (0x7000 | (packet.len() - 115) as u16).to_be_bytes()
+        );
+        assert_eq!(packet[108], 150);
+        assert_eq!(packet[113..115], 7u16.to_be_bytes());
+        assert_eq!(packet[123..125], 13u16.to_be_bytes());
+    }
+
+    #[test]
+    fn sequence_numbers_count_up_in_each_universe_and_wrap() {
+        let mut output = test_output(
+            SacnOutput::new,
+            20,
+            10,
+            &["--sacn-destination", "127.0.0.1", "--sacn-universe", "1"],
+        );
+        let leds = test_leds(200);
+        for expected in (0..=255u8).chain([0]) {
+            let packets = output.frame_packets(&leds, 0);
+            assert_eq!(packets.len(), 2);
+            for (_, packet) in packets {
+                assert_eq!(packet[111], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn frame_split_across_universes_round_trips_through_the_receiver() {
+        // 200 pixels need two universes: 170 pixels in the first and 30 in the second
+        let mut output = test_output(
+            SacnOutput::new,
+            20,
+            10,
+            &["--sacn-destination", "127.0.0.1", "--sacn-universe", "5"],
+        );
+        let leds = test_leds(200);
+        let packets = output.frame_packets(&leds, 0);
+        assert_eq!(
+            packets
+                .iter()
+                .map(|(universe, _)| *universe)
+                .collect::<Vec<_>>(),
+            [5, 6]
+        );
+        assert_eq!(packets[0].1.len(), 126 + 510);
+        assert_eq!(packets[1].1.len(), 126 + 90);
+
+        let mut receiver = SacnReceiver::new(Some(5), 200);
+        let mut frame = vec![0; 200 * 3];
+        assert!(!receiver.handle_packet(&packets[0].1, &mut frame));
+        assert!(receiver.handle_packet(&packets[1].1, &mut frame));
+        assert_eq!(frame, to_rgb_bytes(&leds, 255));
+    }
+
+    #[test]
+    fn receiver_ignores_universes_outside_of_the_frame() {
+        let mut output = test_output(
+            SacnOutput::new,
+            2,
+            1,
+            &["--sacn-destination", "127.0.0.1", "--sacn-universe", "9"],
+        );
+        let packets = output.frame_packets(&test_leds(2), 0);
+
+        let mut receiver = SacnReceiver::new(Some(10), 2);
+        let mut frame = vec![0; 6];
+        assert!(!receiver.handle_packet(&packets[0].1, &mut frame));
+        assert_eq!(frame, [0; 6]);
+    }
+
+    #[test]
+    fn receiver_ignores_preview_data() {
+        let mut output = test_output(
+            SacnOutput::new,
+            2,
+            1,
+            &["--sacn-destination", "127.0.0.1", "--sacn-universe", "1"],
+        );
+        // Option bit 7 marks preview data, which should not be shown
+        let packets = output.frame_packets(&test_leds(2), 0x80);
+
+        let mut receiver = SacnReceiver::new(Some(1), 2);
+        let mut frame = vec![0; 6];
+        assert!(!receiver.handle_packet(&packets[0].1, &mut frame));
+        assert_eq!(frame, [0; 6]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_values_from_configuration_files() {
+        let mut config = test_matrix_config(&["-x", "2", "-y", "2"]);
+        config.sacn.sacn_universe = 0;
+        assert!(SacnOutput::new(&config).is_err());
+
+        config.sacn.sacn_universe = 64000;
+        assert!(SacnOutput::new(&config).is_err());
+
+        config.sacn.sacn_universe = 1;
+        config.sacn.sacn_priority = 201;
+        assert!(SacnOutput::new(&config).is_err());
+
+        let config = test_matrix_config(&["-x", "0", "-y", "2"]);
+        assert!(SacnOutput::new(&config).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::clargs::{MatrixConfigurationArgs, OutputKind};
+use crate::output::output_artnet::ArtNetOutput;
+use crate::output::output_ddp::DdpOutput;
+use crate::output::output_fanout::FanOutOutput;
+use crate::output::output_opc::OpcOutput;
+use crate::output::output_record::RecordOutput;
+use crate::output::output_sacn::SacnOutput;
+use crate::output::output_terminal::TerminalOutput;
+use crate::output::output_threaded::ThreadedOutput;
+use crate::output::output_ws281x::Ws281xOutput;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+
+/// Number of RGB pixels that fit in a single 512 channel DMX universe
+pub(crate) const PIXELS_PER_UNIVERSE: usize = 170;
+
+/// A destination that the matrix update thread renders frames to
+pub(crate) trait OutputSink {
+    /// Push a frame to the output
+    ///
+    /// # Arguments
+    ///
+    /// * `leds` - The color of every LED in the matrix, in strip order
+    ///
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()>;
+
+    /// Turn off every LED driven by the output
+    fn clear(&mut self) -> Result<(), ()>;
+
+    /// Change the brightness that following frames are rendered at
+    ///
+    /// # Arguments
+    ///
+    /// * `brightness` - The new brightness, from 0 to 255
+    ///
+    fn set_brightness(&mut self, brightness: u8);
+}
+
+/// Create the outputs selected by a matrix configuration
+///
+/// If more than one output is selected, every frame is rendered to each of them.
+/// Network outputs then run on their own threads, so that a slow network cannot hold up the other outputs.
+/// Outputs that fail to start are skipped, as long as at least one output starts.
+///
+/// # Arguments
+///
+/// * `config` - The matrix configuration to build the outputs from
+///
+pub(crate) fn new_output_sink(config: &MatrixConfigurationArgs) -> Result<Box<dyn OutputSink>, ()> {
+    if let [output_kind] = config.output[..] {
+        return new_single_output_sink(output_kind, config);
+    }
+
+    let mut outputs = vec![];
+    for output_kind in &config.output {
+        let output = match output_kind {
+            OutputKind::Opc | OutputKind::Sacn | OutputKind::Artnet | OutputKind::Ddp => {
+                ThreadedOutput::new(*output_kind, config)
+                    .map(|output| Box::new(output) as Box<dyn OutputSink>)
+            }
+            _ => new_single_output_sink(*output_kind, config),
+        };
+        match output {
+            Ok(output) => outputs.push((*output_kind, output)),
+            Err(_) => {
+                log::warn!("The {output_kind:?} output will be skipped.");
+            }
+        }
+    }
+
+    if outputs.is_empty() {
+        log::error!("None of the selected outputs could be started.");
+        return Err(());
+    }
+    Ok(Box::new(FanOutOutput::new(outputs)))
+}
+
+/// Create a single output
+///
+/// # Arguments
+///
+/// * `output_kind` - The kind of output to create
+/// * `config` - The matrix configuration to build the output from
+///
+pub(crate) fn new_single_output_sink(
+    output_kind: OutputKind,
+    config: &MatrixConfigurationArgs,
+) -> Result<Box<dyn OutputSink>, ()> {
+    match output_kind {
+        OutputKind::Ws281x => Ok(Box::new(Ws281xOutput::new(config)?)),
+        OutputKind::Record => Ok(Box::new(RecordOutput::new(config)?)),
+        OutputKind::Terminal => Ok(Box::new(TerminalOutput::new(config))),
+        OutputKind::Opc => Ok(Box::new(OpcOutput::new(config)?)),
+        OutputKind::Sacn => Ok(Box::new(SacnOutput::new(config)?)),
+        OutputKind::Artnet => Ok(Box::new(ArtNetOutput::new(config)?)),
+        OutputKind::Ddp => Ok(Box::new(DdpOutput::new(config)?)),
+    }
+}
+
+/// Scale a color by a brightness value, for outputs that have no hardware brightness control
+///
+/// # Arguments
+///
+/// * `color` - The color to scale
+/// * `brightness` - The brightness to scale by, from 0 to 255
+///
+pub(crate) fn scale_brightness(color: [u8; 4], brightness: u8) -> [u8; 4] {
+    color.map(|channel| ((channel as u16 * brightness as u16) / 255) as u8)
+}
+
+/// Convert a BGRA color, as stored in the matrix state, to an RGBA color
+///
+/// # Arguments
+///
+/// * `color` - The BGRA color to convert
+///
+pub(crate) fn bgra_to_rgba(color: [u8; 4]) -> [u8; 4] {
+    [color[2], color[1], color[0], color[3]]
+}
+
+/// Flatten LED colors into a buffer of RGB bytes, as sent by most network protocols
+///
+/// # Arguments
+///
+/// * `leds` - The BGRA color of every LED, in strip order
+/// * `brightness` - The brightness to scale every color by, from 0 to 255
+///
+pub(crate) fn to_rgb_bytes(leds: &[[u8; 4]], brightness: u8) -> Vec<u8> {
+    leds.iter()
+        .flat_map(|color| {
+            let [r, g, b, _] = bgra_to_rgba(scale_brightness(*color, brightness));
+            [r, g, b]
+        })
+        .collect()
+}
+
+/// Open a UDP socket that can send packets to an address
+///
+/// The socket is bound to the same address family as the target, since an IPv4 socket cannot send to an IPv6 address.
+///
+/// # Arguments
+///
+/// * `target` - The address that packets will be sent to
+///
+pub(crate) fn bind_udp_socket(target: IpAddr) -> io::Result<UdpSocket> {
+    match target {
+        IpAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)),
+        IpAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)),
+    }
+}
+
+/// Create an output for a test matrix
+///
+/// # Arguments
+///
+/// * `new_output` - The constructor of the output to create
+/// * `width` - The width of the matrix
+/// * `height` - The height of the matrix
+/// * `args` - Any other command line arguments for the matrix configuration
+///
+#[cfg(test)]
+pub(crate) fn test_output<T>(
+    new_output: impl Fn(&MatrixConfigurationArgs) -> Result<T, ()>,
+    width: usize,
+    height: usize,
+    args: &[&str],
+) -> T {
+    let width = width.to_string();
+    let height = height.to_string();
+    let mut all_args = vec!["-x", &width, "-y", &height];
+    all_args.extend_from_slice(args);
+    new_output(&crate::clargs::test_matrix_config(&all_args)).unwrap()
+}
+
+/// Make a frame where every LED has a different color
+///
+/// # Arguments
+///
+/// * `count` - The number of LEDs in the frame
+///
+#[cfg(test)]
+pub(crate) fn test_leds(count: usize) -> Vec<[u8; 4]> {
+    (0..count)
+        .map(|index| [index as u8, (index >> 8) as u8, 0xaa, 0xff])
+        .collect()
+}
@@ -0,0 +1,129 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::matrix::matrix_map::{MatrixMap, MatrixMapBuilder};
+use crate::output::output_sink::{bgra_to_rgba, scale_brightness, OutputSink};
+use std::fmt::Write as FmtWrite;
+use std::io::{stdout, Write};
+
+/// Character used to draw two vertically stacked pixels in a single terminal cell
+const UPPER_HALF_BLOCK: char = '▀';
+
+/// Draws a live preview of the matrix in the terminal, using truecolor half-blocks
+///
+/// Each terminal cell shows two pixels; the top pixel as the foreground color of a half-block,
+/// and the bottom pixel as the background color.
+pub(crate) struct TerminalOutput {
+    /// Width of the matrix, in number of LEDs
+    width: usize,
+
+    /// Height of the matrix, in number of LEDs
+    height: usize,
+
+    /// Maps positions on the physical matrix to LEDs on the strip, without any mirroring applied
+    physical_map: MatrixMap,
+
+    /// The brightness to apply to the preview
+    brightness: u8,
+
+    /// True once the terminal has been prepared for drawing
+    started: bool,
+}
+
+impl TerminalOutput {
+    /// Create a new terminal output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to set up the preview with
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Self {
+        // Build a map of the wiring alone, so that mirroring shows up in the preview like it would on the matrix
+        let mut physical_map = MatrixMapBuilder::new(config.width, config.height);
+        if config.serpentine {
+            physical_map = physical_map.serpentine();
+        }
+        if config.vertical {
+            physical_map = physical_map.vertical();
+        }
+
+        Self {
+            width: config.width,
+            height: config.height,
+            physical_map: physical_map.build(),
+            brightness: config.brightness,
+            started: false,
+        }
+    }
+
+    /// Get the RGB color of the LED at a position on the physical matrix
+    fn pixel(&self, leds: &[[u8; 4]], x: usize, y: usize) -> [u8; 3] {
+        let color = leds
+            .get(self.physical_map.get(x, y))
+            .copied()
+            .unwrap_or([0; 4]);
+        let [r, g, b, _] = bgra_to_rgba(scale_brightness(color, self.brightness));
+        [r, g, b]
+    }
+}
+
+impl OutputSink for TerminalOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        let mut frame = String::new();
+
+        // Clear the screen and hide the cursor before the first frame, and return to the top left for every frame
+        if !self.started {
+            frame.push_str("\x1b[2J\x1b[?25l");
+            self.started = true;
+        }
+        frame.push_str("\x1b[H");
+
+        for y in (0..self.height).step_by(2) {
+            for x in 0..self.width {
+                let [tr, tg, tb] = self.pixel(leds, x, y);
+                let _ = write!(frame, "\x1b[38;2;{tr};{tg};{tb}m");
+
+                // With an odd height, the last row of cells only has a top pixel
+                if y + 1 < self.height {
+                    let [br, bg, bb] = self.pixel(leds, x, y + 1);
+                    let _ = write!(frame, "\x1b[48;2;{br};{bg};{bb}m");
+                } else {
+                    frame.push_str("\x1b[49m");
+                }
+                frame.push(UPPER_HALF_BLOCK);
+            }
+            frame.push_str("\x1b[0m\n");
+        }
+
+        let mut stdout = stdout().lock();
+        match stdout
+            .write_all(frame.as_bytes())
+            .and_then(|_| stdout.flush())
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                log::error!("Failed to draw matrix preview to the terminal.");
+                log::debug!("Failed with the following error: {e:?}");
+                Err(())
+            }
+        }
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        let leds = vec![[0; 4]; self.width * self.height];
+        self.render(&leds)
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+}
+
+impl Drop for TerminalOutput {
+    fn drop(&mut self) {
+        // Give the cursor back to the terminal
+        if self.started {
+            let mut stdout = stdout().lock();
+            let _ = stdout.write_all(b"\x1b[?25h");
+            let _ = stdout.flush();
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::clargs::{MatrixConfigurationArgs, OutputKind};
+use crate::output::output_sink::{new_single_output_sink, OutputSink};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the output thread to clear the output or shut down
+const THREADED_OUTPUT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often to retry handing a command to a busy output thread
+const THREADED_OUTPUT_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A request for the output thread
+enum OutputCommand {
+    /// Render a frame
+    Render(Vec<[u8; 4]>),
+
+    /// Clear the output, and send back the result
+    Clear(SyncSender<Result<(), ()>>),
+}
+
+/// Runs an output on its own thread, so that a slow output cannot hold up any other output
+///
+/// Only one frame is queued for the output at a time.
+/// Frames that arrive while the output is still busy with the last one are dropped.
+pub(crate) struct ThreadedOutput {
+    /// The kind of output, for logging
+    kind: OutputKind,
+
+    /// Sends commands to the output thread
+    command_sender: Option<SyncSender<OutputCommand>>,
+
+    /// Told when the output thread has finished
+    finished_receiver: Receiver<()>,
+
+    /// The brightness that the output thread should render frames at
+    brightness: Arc<AtomicU8>,
+
+    /// True if the last frame the output thread rendered failed
+    failing: Arc<AtomicBool>,
+
+    /// True if the last frame was dropped because the output was busy
+    dropping_frames: bool,
+}
+
+impl ThreadedOutput {
+    /// Start an output on its own thread
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of output to start
+    /// * `config` - The matrix configuration to build the output from
+    ///
+    pub(crate) fn new(kind: OutputKind, config: &MatrixConfigurationArgs) -> Result<Self, ()> {
+        let (command_sender, command_receiver) = mpsc::sync_channel::<OutputCommand>(1);
+        let (started_sender, started_receiver) = mpsc::sync_channel::<Result<(), ()>>(1);
+        let (finished_sender, finished_receiver) = mpsc::sync_channel::<()>(1);
+        let brightness = Arc::new(AtomicU8::new(config.brightness));
+        let failing = Arc::new(AtomicBool::new(false));
+
+        let config = config.clone();
+        let thread_brightness = Arc::clone(&brightness);
+        let thread_failing = Arc::clone(&failing);
+        let spawn_result = thread::Builder::new()
+            .name(format!("{kind:?} output").to_lowercase())
+            .spawn(move || {
+                // Create the output on this thread, since not every output can be moved between threads
+                let mut output = match new_single_output_sink(kind, &config) {
+                    Ok(output) => {
+                        let _ = started_sender.send(Ok(()));
+                        output
+                    }
+                    Err(_) => {
+                        let _ = started_sender.send(Err(()));
+                        return;
+                    }
+                };
+                let mut output_brightness = config.brightness;
+
+                // Run commands until the output is dropped
+                for command in command_receiver {
+                    match command {
+                        OutputCommand::Render(leds) => {
+                            let new_brightness = thread_brightness.load(Ordering::Relaxed);
+                            if new_brightness != output_brightness {
+                                output.set_brightness(new_brightness);
+                                output_brightness = new_brightness;
+                            }
+                            let failed = output.render(&leds).is_err();
+                            thread_failing.store(failed, Ordering::Relaxed);
+                        }
+                        OutputCommand::Clear(result_sender) => {
+                            let _ = result_sender.send(output.clear());
+                        }
+                    }
+                }
+
+                // Let the output finish up before saying the thread is done
+                drop(output);
+                let _ = finished_sender.send(());
+            });
+        if let Err(e) = spawn_result {
+            log::error!("Failed to start a thread for the {kind:?} output.");
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+
+        // Wait for the output to start, so that outputs that fail to start can be skipped
+        match started_receiver.recv() {
+            Ok(Ok(())) => Ok(Self {
+                kind,
+                command_sender: Some(command_sender),
+                finished_receiver,
+                brightness,
+                failing,
+                dropping_frames: false,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl OutputSink for ThreadedOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        let Some(command_sender) = &self.command_sender else {
+            return Err(());
+        };
+
+        // Hand the frame over without waiting, dropping it if the output is still busy
+        match command_sender.try_send(OutputCommand::Render(leds.to_vec())) {
+            Ok(_) => {
+                if self.dropping_frames {
+                    log::info!("The {:?} output has caught up.", self.kind);
+                    self.dropping_frames = false;
+                }
+            }
+            Err(TrySendError::Full(_)) => {
+                if !self.dropping_frames {
+                    log::warn!(
+                        "The {:?} output is falling behind. Frames will be dropped until it catches up.",
+                        self.kind
+                    );
+                    self.dropping_frames = true;
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                log::error!("The {:?} output thread has stopped.", self.kind);
+                return Err(());
+            }
+        }
+
+        if self.failing.load(Ordering::Relaxed) {
+            return Err(());
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        let Some(command_sender) = &self.command_sender else {
+            return Err(());
+        };
+
+        // Wait a limited time for the output to take the command, so that a stuck output cannot stop Matricks from quitting
+        let deadline = Instant::now() + THREADED_OUTPUT_TIMEOUT;
+        let (result_sender, result_receiver) = mpsc::sync_channel(1);
+        let mut command = OutputCommand::Clear(result_sender);
+        loop {
+            match command_sender.try_send(command) {
+                Ok(_) => break,
+                Err(TrySendError::Full(returned_command)) if Instant::now() < deadline => {
+                    command = returned_command;
+                    thread::sleep(THREADED_OUTPUT_RETRY_INTERVAL);
+                }
+                Err(_) => {
+                    log::warn!("The {:?} output did not respond in time.", self.kind);
+                    return Err(());
+                }
+            }
+        }
+
+        match result_receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(result) => result,
+            Err(_) => {
+                log::warn!("The {:?} output did not respond in time.", self.kind);
+                Err(())
+            }
+        }
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness.store(brightness, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ThreadedOutput {
+    fn drop(&mut self) {
+        // Closing the command channel stops the output thread once it is done with its last command
+        self.command_sender = None;
+        if self
+            .finished_receiver
+            .recv_timeout(THREADED_OUTPUT_TIMEOUT)
+            .is_err()
+        {
+            log::warn!("The {:?} output did not shut down in time.", self.kind);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clargs::test_matrix_config;
+    use std::net::TcpListener;
+
+    #[test]
+    fn slow_output_does_not_block_rendering() {
+        // An OPC server that accepts the connection but never reads, so the output eventually blocks on writes
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = test_matrix_config(&[
+            "-x",
+            "64",
+            "-y",
+            "64",
+            "--opc-address",
+            &listener.local_addr().unwrap().to_string(),
+        ]);
+        let mut output = ThreadedOutput::new(OutputKind::Opc, &config).unwrap();
+        let leds = vec![[255; 4]; 64 * 64];
+        output.render(&leds).unwrap();
+        let _connection = listener.accept().unwrap();
+
+        // Keep rendering until the socket buffers fill up and frames start being dropped
+        let start = Instant::now();
+        while !output.dropping_frames && start.elapsed() < Duration::from_secs(5) {
+            let render_start = Instant::now();
+            let _ = output.render(&leds);
+            assert!(render_start.elapsed() < Duration::from_millis(100));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(output.dropping_frames);
+    }
+}
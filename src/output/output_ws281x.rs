@@ -0,0 +1,79 @@
+use crate::clargs::MatrixConfigurationArgs;
+use crate::output::output_sink::OutputSink;
+use rs_ws281x::{ChannelBuilder, Controller, ControllerBuilder, StripType, WS2811Error};
+
+/// Drives WS281x LEDs connected to the GPIO of a Raspberry Pi
+pub(crate) struct Ws281xOutput {
+    /// The rs_ws281x LED controller
+    controller: Controller,
+}
+
+impl Ws281xOutput {
+    /// Create a new WS281x output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The matrix configuration to set up the LED controller with
+    ///
+    pub(crate) fn new(config: &MatrixConfigurationArgs) -> Result<Self, ()> {
+        let controller = match ControllerBuilder::new()
+            .freq(config.controller.frequency)
+            .dma(config.controller.dma as i32)
+            .channel(
+                0, // channel index
+                ChannelBuilder::new()
+                    .pin(config.controller.gpio as i32)
+                    .count((config.width * config.height) as i32)
+                    .strip_type(StripType::Ws2812)
+                    .brightness(config.brightness)
+                    .build(),
+            )
+            .build()
+        {
+            Ok(controller) => controller,
+            Err(e) => {
+                log::error!("Failed to create LED controller.");
+                log::debug!("Failed with the following error: {e}");
+                return Err(());
+            }
+        };
+
+        Ok(Self { controller })
+    }
+
+    /// Push the current contents of the LED buffer to the LEDs
+    fn push(&mut self) -> Result<(), ()> {
+        match self.controller.render() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                log::error!("Failed to push changes to matrix.");
+                log::debug!("Failed with the following error: {e}");
+
+                if let WS2811Error::SpiTransfer = e {
+                    log::warn!("Failed to transfer data to LEDs. It is possible that too few LEDs are connected, or the SPI buffer is too small.");
+                }
+                Err(())
+            }
+        }
+    }
+}
+
+impl OutputSink for Ws281xOutput {
+    fn render(&mut self, leds: &[[u8; 4]]) -> Result<(), ()> {
+        for (led, color) in self.controller.leds_mut(0).iter_mut().zip(leds) {
+            *led = *color;
+        }
+        self.push()
+    }
+
+    fn clear(&mut self) -> Result<(), ()> {
+        for led in self.controller.leds_mut(0) {
+            *led = [0, 0, 0, 0];
+        }
+        self.push()
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.controller.set_brightness(0, brightness);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod playlist_entry;
+pub mod playlist_expand;
+pub mod playlist_order;
+pub mod playlist_schedule;
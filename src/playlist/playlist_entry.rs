@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// A plugin in the playlist, along with the settings and configuration values given only to that plugin
+///
+/// In a configuration file, an entry is either the path to a plugin,
+/// or a table with the path to a plugin, its settings, and a `config` table.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "PlaylistEntryForm", into = "PlaylistEntryForm")]
+pub struct PlaylistEntry {
+    /// Path to the plugin's WASM file
+    pub path: String,
+
+    /// Configuration values given to this plugin, on top of the values given to every plugin
+    pub config: BTreeMap<String, toml::Value>,
+
+    /// Maximum time (in seconds) that this plugin can run, instead of the time limit for every plugin
+    pub time_limit: Option<u64>,
+
+    /// Minimum time (in seconds) that this plugin is shown for, holding its last frame if it finishes early
+    pub min_duration: Option<u64>,
+
+    /// Number of times in a row that this plugin is played on each pass through the playlist
+    pub repeat: u32,
+
+    /// Framerate to run this plugin at, instead of the framerate of the matrix. Always greater than 0.
+    pub fps: Option<f32>,
+
+    /// Brightness of the matrix while this plugin is running, instead of the brightness of the matrix
+    pub brightness: Option<u8>,
+
+    /// False if this plugin should be left out of the playlist
+    pub enabled: bool,
+
+    /// How often this plugin is picked compared to the others, when the playlist is played in weighted random order
+    pub weight: f64,
+}
+
+/// The ways that a playlist entry can be written in a configuration file
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PlaylistEntryForm {
+    /// Just the path to the plugin
+    Path(String),
+
+    /// The path to the plugin, along with its settings and configuration values
+    Table {
+        path: String,
+
+        #[serde(default)]
+        config: BTreeMap<String, toml::Value>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        time_limit: Option<u64>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_duration: Option<u64>,
+
+        #[serde(default = "default_repeat")]
+        repeat: u32,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fps: Option<f32>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        brightness: Option<u8>,
+
+        #[serde(default = "default_enabled")]
+        enabled: bool,
+
+        #[serde(default = "default_weight")]
+        weight: f64,
+    },
+}
+
+/// Number of times an entry is played in a row, when the entry does not say
+fn default_repeat() -> u32 {
+    1
+}
+
+/// Whether an entry is in the playlist, when the entry does not say
+fn default_enabled() -> bool {
+    true
+}
+
+/// How often an entry is picked in weighted random order, when the entry does not say
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl TryFrom<PlaylistEntryForm> for PlaylistEntry {
+    type Error = String;
+
+    fn try_from(form: PlaylistEntryForm) -> Result<Self, Self::Error> {
+        match form {
+            PlaylistEntryForm::Path(path) => Ok(Self::from_path(path)),
+            PlaylistEntryForm::Table {
+                path,
+                config,
+                time_limit,
+                min_duration,
+                repeat,
+                fps,
+                brightness,
+                enabled,
+                weight,
+            } => {
+                // A plugin cannot be updated at a framerate of 0 or less
+                if let Some(fps) = fps {
+                    if !(fps > 0.0 && fps.is_finite()) {
+                        return Err(format!(
+                            "the fps of plugin \"{path}\" is {fps}, but must be greater than 0"
+                        ));
+                    }
+                }
+
+                // A weight has to be a number that a plugin can be picked in proportion to
+                if !(weight >= 0.0 && weight.is_finite()) {
+                    return Err(format!(
+                        "the weight of plugin \"{path}\" is {weight}, but must be a number of 0 or more"
+                    ));
+                }
+
+                Ok(Self {
+                    path,
+                    config,
+                    time_limit,
+                    min_duration,
+                    repeat,
+                    fps,
+                    brightness,
+                    enabled,
+                    weight,
+                })
+            }
+        }
+    }
+}
+
+impl From<PlaylistEntry> for PlaylistEntryForm {
+    fn from(entry: PlaylistEntry) -> Self {
+        // Keep entries without any settings or configuration values short
+        if entry.is_plain() {
+            Self::Path(entry.path)
+        } else {
+            Self::Table {
+                path: entry.path,
+                config: entry.config,
+                time_limit: entry.time_limit,
+                min_duration: entry.min_duration,
+                repeat: entry.repeat,
+                fps: entry.fps,
+                brightness: entry.brightness,
+                enabled: entry.enabled,
+                weight: entry.weight,
+            }
+        }
+    }
+}
+
+impl FromStr for PlaylistEntry {
+    type Err = Infallible;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_path(path.to_string()))
+    }
+}
+
+impl PlaylistEntry {
+    /// Create an entry for the plugin at a path, without any settings or configuration values
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the plugin's WASM file
+    ///
+    pub fn from_path(path: String) -> Self {
+        Self {
+            path,
+            config: BTreeMap::new(),
+            time_limit: None,
+            min_duration: None,
+            repeat: default_repeat(),
+            fps: None,
+            brightness: None,
+            enabled: default_enabled(),
+            weight: default_weight(),
+        }
+    }
+
+    /// Get the framerate to run this plugin at
+    ///
+    /// The matrix is only updated at its own framerate, so an entry cannot run its plugin any faster than that.
+    ///
+    /// # Arguments
+    ///
+    /// * `matrix_fps` - The framerate of the matrix
+    ///
+    pub fn frame_rate(&self, matrix_fps: f32) -> f32 {
+        self.fps.map_or(matrix_fps, |fps| fps.min(matrix_fps))
+    }
+
+    /// Check if this entry only has a path, so that it can be written as just the path
+    fn is_plain(&self) -> bool {
+        self.config.is_empty()
+            && self.time_limit.is_none()
+            && self.min_duration.is_none()
+            && self.repeat == default_repeat()
+            && self.fps.is_none()
+            && self.brightness.is_none()
+            && self.enabled == default_enabled()
+            && self.weight == default_weight()
+    }
+
+    /// Get the configuration values of this entry, as they are given to the plugin
+    ///
+    /// Strings are given as they are, and any other values are given as JSON.
+    pub fn config_strings(&self) -> BTreeMap<String, Option<String>> {
+        self.config
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    toml::Value::String(string) => string.clone(),
+                    value => serde_json::to_string(value).unwrap_or_default(),
+                };
+                (key.clone(), Some(value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A configuration file section with a playlist
+    #[derive(Deserialize)]
+    struct TestPlaylist {
+        plugin: Vec<PlaylistEntry>,
+    }
+
+    #[test]
+    fn rejects_entries_that_are_not_faster_than_zero_fps() {
+        for fps in ["0.0", "-5.0", "nan"] {
+            let toml = format!("plugin = [{{ path = \"a.wasm\", fps = {fps} }}]");
+            assert!(toml::from_str::<TestPlaylist>(&toml).is_err());
+        }
+
+        let toml = "plugin = [\"a.wasm\", { path = \"b.wasm\", fps = 0.5 }]";
+        let playlist = toml::from_str::<TestPlaylist>(toml).unwrap();
+        assert_eq!(playlist.plugin[1].fps, Some(0.5));
+    }
+
+    #[test]
+    fn rejects_weights_that_are_negative_or_not_finite() {
+        for weight in ["-1.0", "inf", "nan"] {
+            let toml = format!("plugin = [{{ path = \"a.wasm\", weight = {weight} }}]");
+            assert!(toml::from_str::<TestPlaylist>(&toml).is_err());
+        }
+
+        let toml = "plugin = [{ path = \"a.wasm\", weight = 0.0 }]";
+        let playlist = toml::from_str::<TestPlaylist>(toml).unwrap();
+        assert_eq!(playlist.plugin[0].weight, 0.0);
+    }
+
+    #[test]
+    fn frame_rate_is_capped_at_the_matrix_framerate() {
+        let mut entry = PlaylistEntry::from_path(String::from("a.wasm"));
+        assert_eq!(entry.frame_rate(30.0), 30.0);
+
+        entry.fps = Some(10.0);
+        assert_eq!(entry.frame_rate(30.0), 10.0);
+
+        entry.fps = Some(120.0);
+        assert_eq!(entry.frame_rate(30.0), 30.0);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::playlist::playlist_entry::PlaylistEntry;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extension of the plugin files that are picked up from a directory
+const PLUGIN_EXTENSION: &str = "wasm";
+
+/// Key that tells a plugin apart from every other plugin in every playlist: its playlist's name, the position of its entry and its path
+pub(crate) type PlaylistKey = (String, usize, String);
+
+/// A plugin to play on one pass through the playlist
+#[derive(Clone)]
+pub(crate) struct PlaylistItem {
+    /// Name of the playlist that this plugin was found in
+    pub(crate) playlist_name: String,
+
+    /// Position of the playlist entry that this plugin was found from
+    pub(crate) entry_index: usize,
+
+    /// The playlist entry, with the path to this plugin's WASM file
+    pub(crate) entry: PlaylistEntry,
+}
+
+impl PlaylistItem {
+    /// Get the key that tells this plugin apart from every other plugin in every playlist
+    pub(crate) fn key(&self) -> PlaylistKey {
+        (
+            self.playlist_name.clone(),
+            self.entry_index,
+            self.entry.path.clone(),
+        )
+    }
+}
+
+/// Find the plugins to play on a pass through the playlist
+///
+/// Disabled entries are left out, and each plugin is played as many times in a row as its entry asks for.
+/// Entries that are a directory are replaced by every WASM file in the directory,
+/// and entries that are a glob pattern are replaced by every file matching the pattern.
+/// The plugins found from each entry are sorted by path, so that every pass plays them in the same order.
+/// Any other entry is played as it is.
+///
+/// # Arguments
+///
+/// * `playlist_name` - The name of the playlist
+/// * `playlist` - The entries of the playlist
+///
+pub(crate) fn expand_playlist(
+    playlist_name: &str,
+    playlist: &[PlaylistEntry],
+) -> Vec<PlaylistItem> {
+    let mut items = vec![];
+    for (entry_index, entry) in playlist.iter().enumerate() {
+        if !entry.enabled {
+            log::debug!(
+                "Leaving disabled playlist entry \"{}\" out of the playlist.",
+                entry.path
+            );
+            continue;
+        }
+
+        let path = Path::new(&entry.path);
+        let mut plugin_paths = if path.is_dir() {
+            directory_plugins(path)
+        } else if is_glob_pattern(&entry.path) {
+            glob_plugins(&entry.path)
+        } else {
+            // Let the plugin host report any problem with a plain path
+            vec![PathBuf::from(&entry.path)]
+        };
+
+        if plugin_paths.is_empty() {
+            log::warn!("No plugins were found at \"{}\".", entry.path);
+            continue;
+        }
+
+        plugin_paths.sort();
+        for plugin_path in plugin_paths {
+            let plugin_entry = PlaylistEntry {
+                path: plugin_path.to_string_lossy().to_string(),
+                ..entry.clone()
+            };
+            for _ in 0..entry.repeat {
+                items.push(PlaylistItem {
+                    playlist_name: playlist_name.to_string(),
+                    entry_index,
+                    entry: plugin_entry.clone(),
+                });
+            }
+        }
+    }
+
+    items
+}
+
+/// Check if a playlist path is a glob pattern
+///
+/// # Arguments
+///
+/// * `path` - The path of the playlist entry
+///
+fn is_glob_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Find every plugin in a directory
+///
+/// # Arguments
+///
+/// * `directory` - The directory to look for plugins in
+///
+fn directory_plugins(directory: &Path) -> Vec<PathBuf> {
+    let directory_entries = match fs::read_dir(directory) {
+        Ok(directory_entries) => directory_entries,
+        Err(e) => {
+            log::warn!(
+                "Failed to read plugin directory \"{}\".",
+                directory.display()
+            );
+            log::debug!("Failed with the following error: {e:?}");
+            return vec![];
+        }
+    };
+
+    directory_entries
+        .filter_map(|directory_entry| directory_entry.ok())
+        .map(|directory_entry| directory_entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == PLUGIN_EXTENSION)
+        })
+        .collect()
+}
+
+/// Find every plugin matching a glob pattern
+///
+/// # Arguments
+///
+/// * `pattern` - The glob pattern to match plugin files against
+///
+fn glob_plugins(pattern: &str) -> Vec<PathBuf> {
+    let paths = match glob::glob(pattern) {
+        Ok(paths) => paths,
+        Err(e) => {
+            log::warn!("Invalid plugin glob pattern \"{pattern}\".");
+            log::debug!("Failed with the following error: {e:?}");
+            return vec![];
+        }
+    };
+
+    paths
+        .filter_map(|path| path.ok())
+        .filter(|path| path.is_file())
+        .collect()
+}
@@ -0,0 +1,222 @@
+use crate::clargs::{PlaylistOrder, PluginConfigurationArgs};
+use crate::playlist::playlist_expand::{PlaylistItem, PlaylistKey};
+use rand::seq::SliceRandom;
+use std::collections::VecDeque;
+
+/// Decides the order that plugins are played in on each pass through the playlist
+pub(crate) struct PlaylistOrderer {
+    /// Order to play the plugins in
+    order: PlaylistOrder,
+
+    /// Number of recently played plugins to avoid playing again, when playing in a random order
+    avoid_last: usize,
+
+    /// The most recently picked plugins, newest last
+    recent: VecDeque<PlaylistKey>,
+}
+
+impl PlaylistOrderer {
+    /// Create a new playlist orderer
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The plugin configuration, with the order to play plugins in
+    ///
+    pub(crate) fn new(config: &PluginConfigurationArgs) -> Self {
+        if config.avoid_last > 0 && config.order == PlaylistOrder::Sequential {
+            log::info!("Recently played plugins are only avoided when the playlist is played in a random order.");
+        }
+
+        Self {
+            order: config.order,
+            avoid_last: config.avoid_last,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Put the plugins found on a pass through the playlist in the order they should be played
+    ///
+    /// In shuffle order, every plugin is played once in a random order.
+    /// In weighted order, as many plugins as were found are picked at random, so some may be played more than once.
+    /// The repeats of a plugin are kept together, so that it is still played as many times in a row as its entry asks for.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The plugins found on this pass through the playlist
+    ///
+    pub(crate) fn next_pass(&mut self, items: Vec<PlaylistItem>) -> Vec<PlaylistItem> {
+        // Group the repeats of each plugin into a run, which is ordered as one plugin
+        let mut runs: Vec<Vec<PlaylistItem>> = vec![];
+        for item in items {
+            match runs.last_mut() {
+                Some(run) if run[0].key() == item.key() => run.push(item),
+                _ => runs.push(vec![item]),
+            }
+        }
+
+        let picks = match self.order {
+            PlaylistOrder::Sequential => runs,
+            PlaylistOrder::Shuffle => {
+                let mut picks = vec![];
+                while let Some(index) = self.pick(&runs, |_| 1.0) {
+                    picks.push(runs.swap_remove(index));
+                }
+                picks
+            }
+            PlaylistOrder::Weighted => {
+                let mut picks = vec![];
+                for _ in 0..runs.len() {
+                    match self.pick(&runs, |run| run[0].entry.weight) {
+                        Some(index) => picks.push(runs[index].clone()),
+                        None => {
+                            log::warn!("Every plugin in the playlist has a weight of 0, so none of them can be picked.");
+                            break;
+                        }
+                    }
+                }
+                picks
+            }
+        };
+
+        picks.into_iter().flatten().collect()
+    }
+
+    /// Pick a plugin at random, avoiding the most recently picked plugins if there are any others to pick
+    ///
+    /// Returns the index of the picked plugin, or `None` if no plugin can be picked.
+    ///
+    /// # Arguments
+    ///
+    /// * `runs` - The plugins to pick from, each with all of its repeats
+    /// * `weight` - How often each plugin should be picked compared to the others
+    ///
+    fn pick(
+        &mut self,
+        runs: &[Vec<PlaylistItem>],
+        weight: impl Fn(&[PlaylistItem]) -> f64,
+    ) -> Option<usize> {
+        let pickable: Vec<usize> = (0..runs.len())
+            .filter(|index| weight(&runs[*index]) > 0.0)
+            .collect();
+        let fresh: Vec<usize> = pickable
+            .iter()
+            .copied()
+            .filter(|index| !self.recent.contains(&runs[*index][0].key()))
+            .collect();
+        let candidates = if fresh.is_empty() { pickable } else { fresh };
+
+        let index = *candidates
+            .choose_weighted(&mut rand::thread_rng(), |index| weight(&runs[*index]))
+            .ok()?;
+
+        // Remember the pick, so that it can be avoided for the next few picks
+        if self.avoid_last > 0 {
+            self.recent.push_back(runs[index][0].key());
+            while self.recent.len() > self.avoid_last {
+                self.recent.pop_front();
+            }
+        }
+
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::playlist_entry::PlaylistEntry;
+    use crate::playlist::playlist_expand::expand_playlist;
+
+    /// Make a playlist of plugins, each repeated a number of times
+    fn test_playlist(repeats: &[u32]) -> Vec<PlaylistEntry> {
+        repeats
+            .iter()
+            .enumerate()
+            .map(|(index, repeat)| PlaylistEntry {
+                repeat: *repeat,
+                ..PlaylistEntry::from_path(format!("plugin-{index}.wasm"))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn repeats_stay_in_a_row_in_every_order() {
+        let playlist = test_playlist(&[3, 1, 2, 1]);
+        for order in [
+            PlaylistOrder::Sequential,
+            PlaylistOrder::Shuffle,
+            PlaylistOrder::Weighted,
+        ] {
+            let mut orderer = PlaylistOrderer {
+                order,
+                avoid_last: 0,
+                recent: VecDeque::new(),
+            };
+            for _ in 0..20 {
+                let pass = orderer.next_pass(expand_playlist("", &playlist));
+                let mut runs: Vec<(usize, usize)> = vec![];
+                for item in &pass {
+                    match runs.last_mut() {
+                        Some((index, count)) if *index == item.entry_index => *count += 1,
+                        _ => runs.push((item.entry_index, 1)),
+                    }
+                }
+                // Weighted order may pick a plugin twice in a row, which joins its two runs
+                if order != PlaylistOrder::Weighted {
+                    assert_eq!(runs.len(), 4, "{order:?}");
+                }
+                for (index, count) in runs {
+                    assert_eq!(count % playlist[index].repeat as usize, 0, "{order:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_orders_avoid_the_last_picked_plugins() {
+        let playlist = test_playlist(&[1, 1, 1, 1]);
+        for order in [PlaylistOrder::Shuffle, PlaylistOrder::Weighted] {
+            let mut orderer = PlaylistOrderer {
+                order,
+                avoid_last: 3,
+                recent: VecDeque::new(),
+            };
+            let mut picks = vec![];
+            for _ in 0..20 {
+                let pass = orderer.next_pass(expand_playlist("", &playlist));
+                picks.extend(pass.iter().map(|item| item.entry_index));
+            }
+
+            // With the last three plugins avoided, every four plugins in a row are all different, even across passes
+            assert_eq!(picks.len(), 80, "{order:?}");
+            for window in picks.windows(4) {
+                let mut window = window.to_vec();
+                window.sort();
+                assert_eq!(window, [0, 1, 2, 3], "{order:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_order_never_picks_plugins_with_a_weight_of_zero() {
+        let mut playlist = test_playlist(&[1, 1, 1]);
+        playlist[0].weight = 2.0;
+        playlist[1].weight = 0.0;
+        let mut orderer = PlaylistOrderer {
+            order: PlaylistOrder::Weighted,
+            avoid_last: 1,
+            recent: VecDeque::new(),
+        };
+        for _ in 0..50 {
+            let pass = orderer.next_pass(expand_playlist("", &playlist));
+            assert_eq!(pass.len(), 3);
+            assert!(pass.iter().all(|item| item.entry_index != 1));
+        }
+
+        // Nothing is played if every plugin has a weight of 0
+        for entry in &mut playlist {
+            entry.weight = 0.0;
+        }
+        assert!(orderer.next_pass(expand_playlist("", &playlist)).is_empty());
+    }
+}
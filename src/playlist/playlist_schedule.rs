@@ -0,0 +1,220 @@
+use chrono::{Datelike, Local, NaiveTime, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::playlist::playlist_entry::PlaylistEntry;
+
+/// Name given to the playlist of `plugin` entries, which plays whenever no schedule rule is active
+pub(crate) const MAIN_PLAYLIST: &str = "";
+
+/// Format of the times in a schedule rule
+const TIME_FORMAT: &str = "%H:%M";
+
+/// A rule choosing which playlist is active during part of the day
+///
+/// The rule is active from its start time until its end time, in the local timezone, on the given days.
+/// A rule whose end time is before its start time carries on past midnight,
+/// and a rule whose start and end times are the same is active all day.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ScheduleRule {
+    /// Name of the playlist to play while this rule is active
+    pub playlist: String,
+
+    /// Days of the week that this rule starts on. Every day by default.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<ScheduleDay>,
+
+    /// Time of day that this rule starts at
+    pub start: ScheduleTime,
+
+    /// Time of day that this rule ends at
+    pub end: ScheduleTime,
+}
+
+/// A day of the week in a schedule rule, written as its full or short name
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct ScheduleDay(Weekday);
+
+impl TryFrom<String> for ScheduleDay {
+    type Error = String;
+
+    fn try_from(day: String) -> Result<Self, Self::Error> {
+        day.parse()
+            .map(Self)
+            .map_err(|_| format!("\"{day}\" is not a day of the week"))
+    }
+}
+
+impl From<ScheduleDay> for String {
+    fn from(day: ScheduleDay) -> Self {
+        day.0.to_string().to_lowercase()
+    }
+}
+
+/// A time of day in a schedule rule, written as "HH:MM"
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct ScheduleTime(NaiveTime);
+
+impl TryFrom<String> for ScheduleTime {
+    type Error = String;
+
+    fn try_from(time: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&time, TIME_FORMAT)
+            .map(Self)
+            .map_err(|_| format!("\"{time}\" is not a time of day in the form \"HH:MM\""))
+    }
+}
+
+impl From<ScheduleTime> for String {
+    fn from(time: ScheduleTime) -> Self {
+        time.0.format(TIME_FORMAT).to_string()
+    }
+}
+
+impl ScheduleRule {
+    /// Check if this rule is active at a time
+    ///
+    /// # Arguments
+    ///
+    /// * `weekday` - The day of the week
+    /// * `time` - The time of day
+    ///
+    fn is_active(&self, weekday: Weekday, time: NaiveTime) -> bool {
+        let starts_on =
+            |day: Weekday| self.days.is_empty() || self.days.contains(&ScheduleDay(day));
+        let (start, end) = (self.start.0, self.end.0);
+
+        if start == end {
+            starts_on(weekday)
+        } else if start < end {
+            starts_on(weekday) && start <= time && time < end
+        } else {
+            // The rule carries on past midnight, so it may have started the day before
+            (starts_on(weekday) && time >= start) || (starts_on(weekday.pred()) && time < end)
+        }
+    }
+}
+
+/// Chooses which playlist is active, following the schedule in the configuration
+pub(crate) struct PlaylistSchedule {
+    /// The rules of the schedule, in order of priority
+    rules: Vec<ScheduleRule>,
+}
+
+impl PlaylistSchedule {
+    /// Create a schedule from the rules in the configuration
+    ///
+    /// Rules that refer to a playlist that does not exist are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - The rules of the schedule, in order of priority
+    /// * `playlists` - The named playlists that the rules may choose
+    ///
+    pub(crate) fn new(
+        rules: &[ScheduleRule],
+        playlists: &BTreeMap<String, Vec<PlaylistEntry>>,
+    ) -> Self {
+        let rules = rules
+            .iter()
+            .filter(|rule| {
+                let exists = playlists.contains_key(&rule.playlist);
+                if !exists {
+                    log::warn!(
+                        "The schedule refers to playlist \"{}\", which does not exist. This rule will be ignored.",
+                        rule.playlist
+                    );
+                }
+                exists
+            })
+            .cloned()
+            .collect();
+
+        Self { rules }
+    }
+
+    /// Check if the schedule has any rules
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Get the name of the playlist that should be playing now
+    ///
+    /// The first rule that is active wins. Returns `None` if no rule is active, in which case the main playlist should play.
+    pub(crate) fn active_playlist(&self) -> Option<&str> {
+        let now = Local::now();
+        let time = NaiveTime::from_hms_opt(now.hour(), now.minute(), now.second())?;
+        self.rules
+            .iter()
+            .find(|rule| rule.is_active(now.weekday(), time))
+            .map(|rule| rule.playlist.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a schedule rule from the way it would be written in a configuration file
+    fn test_rule(days: &[&str], start: &str, end: &str) -> ScheduleRule {
+        ScheduleRule {
+            playlist: String::from("night"),
+            days: days
+                .iter()
+                .map(|day| ScheduleDay::try_from(day.to_string()).unwrap())
+                .collect(),
+            start: ScheduleTime::try_from(start.to_string()).unwrap(),
+            end: ScheduleTime::try_from(end.to_string()).unwrap(),
+        }
+    }
+
+    /// Make a time of day
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn rule_is_active_from_its_start_until_its_end() {
+        let rule = test_rule(&[], "09:00", "17:30");
+        assert!(!rule.is_active(Weekday::Mon, time(8, 59)));
+        assert!(rule.is_active(Weekday::Mon, time(9, 0)));
+        assert!(rule.is_active(Weekday::Mon, time(17, 29)));
+        assert!(!rule.is_active(Weekday::Mon, time(17, 30)));
+    }
+
+    #[test]
+    fn rule_carries_on_past_midnight_from_the_day_it_started() {
+        let rule = test_rule(&["fri"], "22:00", "06:00");
+        assert!(rule.is_active(Weekday::Fri, time(22, 0)));
+        assert!(rule.is_active(Weekday::Fri, time(23, 59)));
+        assert!(!rule.is_active(Weekday::Fri, time(3, 0)));
+        assert!(!rule.is_active(Weekday::Fri, time(21, 59)));
+
+        // The part after midnight belongs to the day before
+        assert!(rule.is_active(Weekday::Sat, time(0, 0)));
+        assert!(rule.is_active(Weekday::Sat, time(5, 59)));
+        assert!(!rule.is_active(Weekday::Sat, time(6, 0)));
+        assert!(!rule.is_active(Weekday::Sat, time(22, 0)));
+        assert!(!rule.is_active(Weekday::Thu, time(23, 0)));
+    }
+
+    #[test]
+    fn rule_is_only_active_on_its_days() {
+        let rule = test_rule(&["monday", "wed"], "09:00", "17:00");
+        assert!(rule.is_active(Weekday::Mon, time(12, 0)));
+        assert!(rule.is_active(Weekday::Wed, time(12, 0)));
+        assert!(!rule.is_active(Weekday::Tue, time(12, 0)));
+        assert!(!rule.is_active(Weekday::Sun, time(12, 0)));
+    }
+
+    #[test]
+    fn rule_with_the_same_start_and_end_is_active_all_day() {
+        let rule = test_rule(&["sun"], "07:00", "07:00");
+        assert!(rule.is_active(Weekday::Sun, time(0, 0)));
+        assert!(rule.is_active(Weekday::Sun, time(6, 59)));
+        assert!(rule.is_active(Weekday::Sun, time(23, 59)));
+        assert!(!rule.is_active(Weekday::Mon, time(7, 0)));
+    }
+}
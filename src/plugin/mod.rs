@@ -1 +1,8 @@
+pub mod plugin_cache;
+pub mod plugin_context;
+pub mod plugin_frame;
+pub mod plugin_host;
+pub mod plugin_instance;
 pub mod plugin_logs;
+pub mod plugin_metadata;
+pub mod plugin_watch;
@@ -0,0 +1,117 @@
+use crate::clargs::{InstanceMode, MatricksConfigArgs};
+use crate::playlist::playlist_expand::{PlaylistItem, PlaylistKey};
+use crate::plugin::plugin_context::TeardownReason;
+use crate::plugin::plugin_host::PluginHost;
+use crate::plugin::plugin_instance::PluginInstance;
+use std::collections::BTreeMap;
+use std::mem;
+
+/// Keeps plugins running between passes through the playlist, so that they only have to be compiled once
+pub(crate) struct PluginCache {
+    /// Plugins that are waiting for their next turn, by their playlist, the position of their entry and their path
+    instances: BTreeMap<PlaylistKey, PluginInstance>,
+
+    /// True if plugins should be kept running between passes
+    persistent: bool,
+
+    /// True if plugins should be reloaded when their WASM file changes
+    watch: bool,
+}
+
+impl PluginCache {
+    /// Create a new, empty plugin cache
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Information about how Matricks should run
+    ///
+    pub(crate) fn new(config: &MatricksConfigArgs) -> Self {
+        // A schedule keeps the playlists looping even without the loop flag
+        let looping = config.plugin.loop_plugins || !config.schedule.is_empty();
+        let persistent = config.plugin.instances == InstanceMode::Persistent;
+        if persistent && !looping {
+            log::info!("Plugins are only kept running between passes when the playlist loops.");
+        }
+
+        Self {
+            instances: BTreeMap::new(),
+            persistent: persistent && looping,
+            watch: config.plugin.watch,
+        }
+    }
+
+    /// Get the plugin of a playlist item, ready to be updated
+    ///
+    /// A plugin kept from the last pass is resumed, unless its WASM file has changed and it can be reloaded.
+    /// Any other plugin is loaded and set up.
+    ///
+    /// # Arguments
+    ///
+    /// * `plugin_host` - The host to load plugins with
+    /// * `playlist_item` - The playlist item to get the plugin of
+    ///
+    pub(crate) fn start(
+        &mut self,
+        plugin_host: &PluginHost,
+        playlist_item: &PlaylistItem,
+    ) -> Result<PluginInstance, ()> {
+        let playlist_entry = &playlist_item.entry;
+        match self.instances.remove(&playlist_item.key()) {
+            Some(mut plugin) => {
+                let reloaded = self.watch
+                    && plugin.is_outdated()
+                    && plugin_host.reload(playlist_entry, &mut plugin);
+                if !reloaded {
+                    plugin.resume();
+                }
+                Ok(plugin)
+            }
+            None => {
+                let mut plugin = plugin_host.load(playlist_entry)?;
+                plugin.setup();
+                Ok(plugin)
+            }
+        }
+    }
+
+    /// Take back a plugin once its turn is over
+    ///
+    /// Plugins that ended their turn normally are kept for the next pass if instances are persistent.
+    /// Every other plugin is torn down.
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_item` - The playlist item of the plugin
+    /// * `plugin` - The plugin whose turn is over
+    /// * `reason` - Why the plugin's turn is over
+    ///
+    pub(crate) fn stop(
+        &mut self,
+        playlist_item: &PlaylistItem,
+        mut plugin: PluginInstance,
+        reason: TeardownReason,
+    ) {
+        if self.persistent
+            && matches!(
+                reason,
+                TeardownReason::Finished | TeardownReason::TimeLimit | TeardownReason::Schedule
+            )
+        {
+            self.instances.insert(playlist_item.key(), plugin);
+        } else {
+            plugin.teardown(reason);
+        }
+    }
+
+    /// Tear down every plugin that is waiting for its next turn
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the plugins are being torn down
+    ///
+    pub(crate) fn clear(&mut self, reason: TeardownReason) {
+        for (_, mut plugin) in mem::take(&mut self.instances) {
+            plugin.teardown(reason);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Information about the current frame, given to the update function of a plugin
+#[derive(Serialize)]
+pub(crate) struct FrameContext {
+    /// Number of updates the plugin has been asked for before this one
+    frame: u64,
+
+    /// Time since the plugin started updating, in milliseconds
+    elapsed_ms: f64,
+
+    /// Time since the last update, in milliseconds (0 for the first update)
+    delta_ms: f64,
+
+    /// Wall-clock time, in milliseconds since the Unix epoch
+    unix_time_ms: u64,
+
+    /// Time left before the plugin is stopped by the time limit, in milliseconds, if there is a time limit
+    time_remaining_ms: Option<f64>,
+}
+
+impl FrameContext {
+    /// Create the context for a frame
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Number of updates the plugin has been asked for before this one
+    /// * `elapsed` - Time since the plugin started updating
+    /// * `delta` - Time since the last update
+    /// * `time_limit` - How long the plugin is allowed to run for, if there is a time limit
+    /// * `wall_clock` - The wall-clock time of the frame
+    ///
+    pub(crate) fn new(
+        frame: u64,
+        elapsed: Duration,
+        delta: Duration,
+        time_limit: Option<Duration>,
+        wall_clock: SystemTime,
+    ) -> Self {
+        Self {
+            frame,
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+            delta_ms: delta.as_secs_f64() * 1000.0,
+            unix_time_ms: wall_clock
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            time_remaining_ms: time_limit
+                .map(|time_limit| time_limit.saturating_sub(elapsed).as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+/// Why a plugin is leaving the playlist
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TeardownReason {
+    /// The plugin has no more updates to give
+    Finished,
+
+    /// The plugin has been running for longer than the time limit
+    TimeLimit,
+
+    /// The plugin returned an update that could not be used
+    Error,
+
+    /// The plugin's WASM file has changed, and the plugin is being replaced by the new version
+    Reload,
+
+    /// The schedule has switched to another playlist
+    Schedule,
+
+    /// Matricks is shutting down
+    Shutdown,
+}
+
+/// Information given to the teardown function of a plugin
+#[derive(Serialize)]
+pub(crate) struct TeardownContext {
+    /// Why the plugin is leaving the playlist
+    reason: TeardownReason,
+}
+
+impl TeardownContext {
+    /// Create the context for a teardown
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the plugin is leaving the playlist
+    ///
+    pub(crate) fn new(reason: TeardownReason) -> Self {
+        Self { reason }
+    }
+}
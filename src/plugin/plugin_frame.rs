@@ -0,0 +1,294 @@
+use crate::matrix::matrix_state::{MatrixState, MatrixUpdate, PixelChange};
+use serde::Deserialize;
+use serde_json::from_str;
+use std::str::from_utf8;
+
+/// Frame formats that the host accepts from plugins, as advertised in the plugin config
+pub(crate) const FRAME_FORMATS: &str = "json,binary,delta";
+
+/// Magic bytes that start every binary frame
+const BINARY_FRAME_MAGIC: [u8; 4] = *b"MTRX";
+
+/// Version of the binary frame header
+const BINARY_FRAME_VERSION: u8 = 1;
+
+/// Length of a binary frame header, in bytes
+const BINARY_FRAME_HEADER_LENGTH: usize = 12;
+
+/// Length of the extra header fields of a binary rectangle, in bytes
+const BINARY_RECTANGLE_HEADER_LENGTH: usize = 4;
+
+/// Binary frame flag marking that the pixels only cover a rectangle of the matrix
+const BINARY_FLAG_RECTANGLE: u8 = 0x01;
+
+/// Binary pixel format with four bytes per pixel, in the same order as JSON frames
+const BINARY_PIXEL_FORMAT_BGRA8: u8 = 0;
+
+/// Binary pixel format with three bytes per pixel, in red, green, blue order
+const BINARY_PIXEL_FORMAT_RGB8: u8 = 1;
+
+/// A JSON update that only changes part of the matrix
+#[derive(Deserialize)]
+struct JsonDelta {
+    /// Individual pixels to change, as (x, y, color)
+    #[serde(default)]
+    pixels: Vec<(usize, usize, [u8; 4])>,
+
+    /// Rectangles of pixels to change
+    #[serde(default)]
+    rects: Vec<JsonRectangle>,
+}
+
+/// A rectangle of pixels in a JSON delta update
+#[derive(Deserialize)]
+struct JsonRectangle {
+    /// X-coordinate of the top left corner of the rectangle
+    x: usize,
+
+    /// Y-coordinate of the top left corner of the rectangle
+    y: usize,
+
+    /// The pixels of the rectangle, as rows
+    pixels: MatrixState,
+}
+
+/// Check if a plugin response is a binary frame
+///
+/// JSON responses can never start with the magic bytes, so plugins may switch between formats at any time.
+///
+/// # Arguments
+///
+/// * `data` - The response from the plugin's update function
+///
+pub(crate) fn is_binary_frame(data: &[u8]) -> bool {
+    data.starts_with(&BINARY_FRAME_MAGIC)
+}
+
+/// Read a JSON update returned by a plugin
+///
+/// An update is either an array of rows with the new state of the whole matrix,
+/// an object with the pixels and rectangles that changed, or null if the plugin has no more updates to give.
+///
+/// # Arguments
+///
+/// * `plugin_name` - Name of the plugin that returned the update, as shown in logs
+/// * `data` - The JSON update
+///
+pub(crate) fn parse_json_update(
+    plugin_name: &str,
+    data: &[u8],
+) -> Result<Option<MatrixUpdate>, ()> {
+    // Convert the result form utf8 to &str
+    let json_str = match from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => {
+            log::warn!("Received invalid UTF-8 result from plugin \"{plugin_name}\"");
+            return Err(());
+        }
+    };
+
+    // Full frames are far more common, so only try to read a delta if the update is an object
+    if json_str.trim_start().starts_with('{') {
+        match from_str::<JsonDelta>(json_str) {
+            Ok(delta) => Ok(Some(MatrixUpdate::Delta(delta_changes(
+                plugin_name,
+                delta,
+            )?))),
+            Err(e) => {
+                log::warn!("Received malformed delta update from plugin \"{plugin_name}\"");
+                log::debug!("Failed with the following error: {e:?}");
+                Err(())
+            }
+        }
+    } else {
+        match from_str::<Option<MatrixState>>(json_str) {
+            Ok(matrix_state) => Ok(matrix_state.map(MatrixUpdate::Full)),
+            Err(_) => {
+                log::warn!("Received malformed update from plugin \"{plugin_name}\"");
+                Err(())
+            }
+        }
+    }
+}
+
+/// Flatten a JSON delta update into a list of pixel changes
+///
+/// Fails if a rectangle reaches past the largest coordinate that can be stored.
+///
+/// # Arguments
+///
+/// * `plugin_name` - Name of the plugin that returned the update, as shown in logs
+/// * `delta` - The delta update to flatten
+///
+fn delta_changes(plugin_name: &str, delta: JsonDelta) -> Result<Vec<PixelChange>, ()> {
+    let mut changes: Vec<PixelChange> = delta
+        .pixels
+        .into_iter()
+        .map(|(x, y, color)| PixelChange { x, y, color })
+        .collect();
+
+    for rectangle in delta.rects {
+        for (row_index, row) in rectangle.pixels.iter().enumerate() {
+            for (column_index, color) in row.iter().enumerate() {
+                let (Some(x), Some(y)) = (
+                    rectangle.x.checked_add(column_index),
+                    rectangle.y.checked_add(row_index),
+                ) else {
+                    log::warn!("Received malformed delta update from plugin \"{plugin_name}\"");
+                    log::debug!(
+                        "The rectangle at ({}, {}) reaches past the largest possible coordinate.",
+                        rectangle.x,
+                        rectangle.y
+                    );
+                    return Err(());
+                };
+                changes.push(PixelChange {
+                    x,
+                    y,
+                    color: *color,
+                });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Read a binary frame returned by a plugin
+///
+/// A binary frame is a 12 byte header, followed by the pixels of the frame in row-major order.
+/// The header holds the magic bytes "MTRX", the version (1), the pixel format, a flags byte, a reserved byte,
+/// and then the width and height of the frame as little-endian 16-bit integers.
+/// If the rectangle flag is set, the header is followed by the x and y-coordinates of the top left corner
+/// of the rectangle as little-endian 16-bit integers, and only that rectangle of the matrix is changed.
+///
+/// # Arguments
+///
+/// * `plugin_name` - Name of the plugin that returned the frame, as shown in logs
+/// * `data` - The binary frame
+///
+pub(crate) fn parse_binary_frame(plugin_name: &str, data: &[u8]) -> Result<MatrixUpdate, ()> {
+    let malformed = |problem: String| {
+        log::warn!("Received malformed binary update from plugin \"{plugin_name}\"");
+        log::debug!("The binary update was malformed because {problem}.");
+    };
+
+    if data.len() < BINARY_FRAME_HEADER_LENGTH {
+        malformed(format!("the header is only {} bytes long", data.len()));
+        return Err(());
+    }
+
+    let version = data[4];
+    if version != BINARY_FRAME_VERSION {
+        malformed(format!(
+            "the binary frame version {version} is not supported"
+        ));
+        return Err(());
+    }
+
+    let pixel_format = data[5];
+    let bytes_per_pixel = match pixel_format {
+        BINARY_PIXEL_FORMAT_BGRA8 => 4,
+        BINARY_PIXEL_FORMAT_RGB8 => 3,
+        _ => {
+            malformed(format!("the pixel format {pixel_format} is not supported"));
+            return Err(());
+        }
+    };
+
+    // Read the position of the rectangle, if this frame only covers part of the matrix
+    let is_rectangle = data[6] & BINARY_FLAG_RECTANGLE != 0;
+    let (header_length, rectangle_x, rectangle_y) = if is_rectangle {
+        if data.len() < BINARY_FRAME_HEADER_LENGTH + BINARY_RECTANGLE_HEADER_LENGTH {
+            malformed(format!(
+                "the rectangle header is only {} bytes long",
+                data.len()
+            ));
+            return Err(());
+        }
+        (
+            BINARY_FRAME_HEADER_LENGTH + BINARY_RECTANGLE_HEADER_LENGTH,
+            u16::from_le_bytes([data[12], data[13]]) as usize,
+            u16::from_le_bytes([data[14], data[15]]) as usize,
+        )
+    } else {
+        (BINARY_FRAME_HEADER_LENGTH, 0, 0)
+    };
+
+    let width = u16::from_le_bytes([data[8], data[9]]) as usize;
+    let height = u16::from_le_bytes([data[10], data[11]]) as usize;
+    let pixels = &data[header_length..];
+    if pixels.len() != width * height * bytes_per_pixel {
+        malformed(format!(
+            "a {width}x{height} frame needs {} bytes of pixel data, but {} bytes were given",
+            width * height * bytes_per_pixel,
+            pixels.len()
+        ));
+        return Err(());
+    }
+
+    let colors = pixels
+        .chunks_exact(bytes_per_pixel)
+        .map(|pixel| match pixel_format {
+            BINARY_PIXEL_FORMAT_RGB8 => [pixel[2], pixel[1], pixel[0], 0],
+            _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        });
+
+    if is_rectangle {
+        let mut changes = Vec::with_capacity(width * height);
+        for (index, color) in colors.enumerate() {
+            let (Some(x), Some(y)) = (
+                rectangle_x.checked_add(index % width),
+                rectangle_y.checked_add(index / width),
+            ) else {
+                malformed(format!(
+                    "the rectangle at ({rectangle_x}, {rectangle_y}) reaches past the largest possible coordinate"
+                ));
+                return Err(());
+            };
+            changes.push(PixelChange { x, y, color });
+        }
+        return Ok(MatrixUpdate::Delta(changes));
+    }
+
+    // An empty frame has no rows to split
+    if width == 0 {
+        return Ok(MatrixUpdate::Full(vec![vec![]; height]));
+    }
+
+    let colors: Vec<[u8; 4]> = colors.collect();
+    Ok(MatrixUpdate::Full(
+        colors.chunks_exact(width).map(|row| row.to_vec()).collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_rectangles_that_overflow_the_coordinates() {
+        let update = format!(
+            "{{\"rects\": [{{\"x\": {}, \"y\": 0, \"pixels\": [[[1, 2, 3, 0], [4, 5, 6, 0]]]}}]}}",
+            usize::MAX
+        );
+        assert!(parse_json_update("test", update.as_bytes()).is_err());
+
+        let update = format!(
+            "{{\"rects\": [{{\"x\": 0, \"y\": {}, \"pixels\": [[[1, 2, 3, 0]], [[4, 5, 6, 0]]]}}]}}",
+            usize::MAX
+        );
+        assert!(parse_json_update("test", update.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn places_rectangle_pixels_from_the_corner() {
+        let update = br#"{"rects": [{"x": 3, "y": 4, "pixels": [[[1, 2, 3, 0], [4, 5, 6, 0]]]}]}"#;
+        let Ok(Some(MatrixUpdate::Delta(changes))) = parse_json_update("test", update) else {
+            panic!("the delta update was not read");
+        };
+        let positions: Vec<(usize, usize)> =
+            changes.iter().map(|change| (change.x, change.y)).collect();
+        assert_eq!(positions, [(3, 4), (4, 4)]);
+    }
+}
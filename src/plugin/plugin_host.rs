@@ -0,0 +1,301 @@
+use crate::clargs::MatricksConfigArgs;
+use crate::matrix::matrix_fit::FrameFitter;
+use crate::path_map::PathMap;
+use crate::playlist::playlist_entry::PlaylistEntry;
+use crate::plugin::plugin_context::TeardownReason;
+use crate::plugin::plugin_frame::FRAME_FORMATS;
+use crate::plugin::plugin_instance::PluginInstance;
+use crate::plugin::plugin_logs;
+use crate::plugin::plugin_metadata::HostCapabilities;
+use crate::plugin::plugin_watch::SourceWatcher;
+use extism::manifest::Wasm;
+use extism::{Function, Manifest, Plugin, ValType};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::mem;
+use std::path::Path;
+
+/// Everything that the host provides to the plugins in the playlist
+pub(crate) struct PluginHost {
+    /// Configuration values given to every plugin
+    matricks_config: BTreeMap<String, Option<String>>,
+
+    /// Functions that plugins may call on the host
+    functions: Vec<Function>,
+
+    /// Network hosts that plugins are allowed to access
+    allowed_hosts: Vec<String>,
+
+    /// Paths on the host filesystem that are mapped into the plugin filesystem
+    path_mappings: Vec<PathMap>,
+
+    /// Makes plugin frames fit the matrix
+    frame_fitter: FrameFitter,
+
+    /// Target framerate of the matrix
+    fps: f32,
+}
+
+impl PluginHost {
+    /// Create a new plugin host
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Information about how Matricks should run
+    ///
+    pub(crate) fn new(config: &MatricksConfigArgs) -> Self {
+        // Create the config
+        let mut matricks_config: BTreeMap<String, Option<String>> = BTreeMap::new();
+        matricks_config.insert(
+            String::from("width"),
+            Some(format!("{}", config.matrix.width)),
+        );
+        matricks_config.insert(
+            String::from("height"),
+            Some(format!("{}", config.matrix.height)),
+        );
+        matricks_config.insert(
+            String::from("target_fps"),
+            Some(format!("{}", config.matrix.fps)),
+        );
+        matricks_config.insert(
+            String::from("serpentine"),
+            Some(format!("{}", config.matrix.serpentine)),
+        );
+        matricks_config.insert(
+            String::from("brightness"),
+            Some(format!("{}", config.matrix.brightness)),
+        );
+        matricks_config.insert(
+            String::from("frame_formats"),
+            Some(String::from(FRAME_FORMATS)),
+        );
+
+        // Setup the host functions
+        let plugin_debug_log_function = Function::new(
+            "matricks_debug",
+            [ValType::I64],
+            [],
+            None,
+            plugin_logs::plugin_debug_log,
+        );
+        let plugin_info_log_function = Function::new(
+            "matricks_info",
+            [ValType::I64],
+            [],
+            None,
+            plugin_logs::plugin_info_log,
+        );
+        let plugin_warn_log_function = Function::new(
+            "matricks_warn",
+            [ValType::I64],
+            [],
+            None,
+            plugin_logs::plugin_warn_log,
+        );
+        let plugin_error_log_function = Function::new(
+            "matricks_error",
+            [ValType::I64],
+            [],
+            None,
+            plugin_logs::plugin_error_log,
+        );
+        let functions = vec![
+            plugin_debug_log_function,
+            plugin_info_log_function,
+            plugin_warn_log_function,
+            plugin_error_log_function,
+        ];
+
+        // Process user-supplied path mappings
+        let mut path_mappings: Vec<PathMap> = vec![];
+        match &config.plugin.map_path {
+            None => { /* Do nothing */ }
+            Some(path_map_strings) => {
+                for path_map_string in path_map_strings {
+                    match PathMap::from_string(path_map_string.clone()) {
+                        Ok(path_map) => {
+                            log::info!(
+                                "Mapping local filesystem path \"{}\" to plugin filesystem path \"{}\"",
+                                path_map.from,
+                                path_map.to
+                            );
+                            path_mappings.push(path_map);
+                        }
+                        Err(_) => {
+                            log::warn!("Unable to process path mapping \"{path_map_string}\". This mapping will be ignored.");
+                        }
+                    };
+                }
+            }
+        }
+
+        Self {
+            matricks_config,
+            functions,
+            allowed_hosts: config.plugin.allow_host.clone().unwrap_or_default(),
+            path_mappings,
+            frame_fitter: FrameFitter::new(&config.matrix),
+            fps: config.matrix.fps,
+        }
+    }
+
+    /// Instantiate the plugin of a playlist entry
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_entry` - The playlist entry, with the path to the plugin's WASM file and its configuration values
+    ///
+    pub(crate) fn load(&self, playlist_entry: &PlaylistEntry) -> Result<PluginInstance, ()> {
+        let plugin_path = playlist_entry.path.as_str();
+
+        // Start watching the file before it is read, so that any change made while reading it is noticed
+        let source_watcher = SourceWatcher::new(plugin_path);
+
+        // Get the plugin data at the given path
+        let plugin_data = match fs::read(plugin_path) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Failed to read plugin data at path '{plugin_path}'");
+                log::debug!("Failed with error: {e}");
+                return Err(());
+            }
+        };
+
+        // Pull the filename out from the plugin path
+        let plugin_name = Path::new(plugin_path)
+            .file_name()
+            .unwrap_or(OsStr::new(plugin_path))
+            .to_str()
+            .unwrap_or(plugin_path)
+            .to_string();
+
+        // Make a new manifest for the plugin
+        let mut manifest = Manifest::new([Wasm::data(plugin_data)]);
+
+        // Add the allowed hosts to the manifest
+        for host in self.allowed_hosts.clone() {
+            log::debug!("Adding host \"{host}\" to the manifest.");
+            manifest = manifest.with_allowed_host(host);
+        }
+
+        // Add the path mappings to the manifest
+        for path_map in self.path_mappings.clone() {
+            log::debug!(
+                "Adding mapping from \"{}\" to \"{}\" to the manifest.",
+                path_map.from,
+                path_map.to
+            );
+            manifest = manifest.with_allowed_path(path_map.from, path_map.to);
+        }
+
+        // Make a new instance of the plugin
+        log::info!("Starting plugin \"{plugin_name}\".");
+        let plugin = match Plugin::create_with_manifest(&manifest, self.functions.clone(), true) {
+            Ok(plugin) => plugin,
+            Err(e) => {
+                log::error!("Unable to instantiate plugin \"{plugin_name}\".");
+                log::debug!("Received the following error while attempting to instantiate the plugin: {e:?}");
+                return Err(());
+            }
+        };
+
+        // Tell the plugin about any framerate or brightness that its entry overrides
+        let fps = playlist_entry.frame_rate(self.fps);
+        let mut matricks_config = self.matricks_config.clone();
+        matricks_config.insert(String::from("target_fps"), Some(format!("{fps}")));
+        if let Some(brightness) = playlist_entry.brightness {
+            matricks_config.insert(String::from("brightness"), Some(format!("{brightness}")));
+        }
+
+        // Apply the config to the plugin, with the values set by Matricks replacing any the entry gives for the same keys
+        let mut plugin_config = playlist_entry.config_strings();
+        for (key, value) in matricks_config {
+            if plugin_config.contains_key(&key) {
+                log::warn!("Plugin \"{plugin_name}\" has a config value for \"{key}\", which is set by Matricks. The value from Matricks will be used.");
+            }
+            plugin_config.insert(key, value);
+        }
+        let plugin = match plugin.with_config(&plugin_config) {
+            Ok(plugin) => plugin,
+            Err(e) => {
+                log::error!("Unable to apply configuration to plugin \"{plugin_name}\".");
+                log::debug!("Received the following error while attempting to instantiate the plugin: {e:?}");
+                return Err(());
+            }
+        };
+
+        let mut plugin =
+            PluginInstance::new(plugin, plugin_name, self.frame_fitter, source_watcher);
+
+        // Make sure the plugin can run on this matrix before it is set up
+        let metadata = plugin.read_metadata();
+        metadata.check_compatibility(plugin.name(), &self.capabilities(fps))?;
+
+        Ok(plugin)
+    }
+
+    /// Replace a plugin with a new instance loaded from its WASM file
+    ///
+    /// The old plugin is torn down and the new one is set up. If the new plugin fails to load, the old plugin is kept.
+    /// Returns true if the plugin was replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_entry` - The playlist entry of the plugin
+    /// * `plugin` - The plugin to replace
+    ///
+    pub(crate) fn reload(
+        &self,
+        playlist_entry: &PlaylistEntry,
+        plugin: &mut PluginInstance,
+    ) -> bool {
+        log::info!(
+            "The file of plugin \"{}\" has changed. Reloading the plugin.",
+            plugin.name()
+        );
+
+        let new_plugin = match self.load(playlist_entry) {
+            Ok(new_plugin) => new_plugin,
+            Err(_) => {
+                log::warn!(
+                    "Failed to reload plugin \"{}\". The previous version will keep running.",
+                    plugin.name()
+                );
+                return false;
+            }
+        };
+
+        let mut old_plugin = mem::replace(plugin, new_plugin);
+        old_plugin.teardown(TeardownReason::Reload);
+        plugin.setup();
+        true
+    }
+
+    /// Get what the host offers to plugins
+    ///
+    /// # Arguments
+    ///
+    /// * `fps` - Framerate that the plugin will be run at
+    ///
+    fn capabilities(&self, fps: f32) -> HostCapabilities<'_> {
+        let (width, height) = self.frame_fitter.dimensions();
+        HostCapabilities {
+            width,
+            height,
+            fps,
+            host_functions: self
+                .functions
+                .iter()
+                .map(|function| function.name())
+                .collect(),
+            allowed_hosts: &self.allowed_hosts,
+            paths: self
+                .path_mappings
+                .iter()
+                .map(|path_map| path_map.to.as_str())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use crate::clargs::FitPolicy;
+use crate::matrix::matrix_fit::{FrameFitter, FrameShape};
+use crate::matrix::matrix_state::{MatrixUpdate, PixelChange};
+use crate::plugin::plugin_context::{FrameContext, TeardownContext, TeardownReason};
+use crate::plugin::plugin_frame::{is_binary_frame, parse_binary_frame, parse_json_update};
+use crate::plugin::plugin_metadata::PluginMetadata;
+use crate::plugin::plugin_watch::SourceWatcher;
+use extism::Plugin;
+use serde_json::from_slice;
+
+/// A running plugin
+pub(crate) struct PluginInstance {
+    /// The underlying Extism plugin
+    plugin: Plugin<'static>,
+
+    /// Name of the plugin, as shown in logs
+    name: String,
+
+    /// Makes the plugin's frames fit the matrix
+    frame_fitter: FrameFitter,
+
+    /// Shape of the last frame returned by the plugin, so that each new shape is only logged once
+    last_frame_shape: Option<FrameShape>,
+
+    /// True if the plugin has been warned about changing pixels outside of the matrix
+    warned_changes_outside: bool,
+
+    /// Watches the plugin's WASM file for changes
+    source_watcher: SourceWatcher,
+}
+
+impl PluginInstance {
+    /// Wrap an Extism plugin
+    ///
+    /// # Arguments
+    ///
+    /// * `plugin` - The Extism plugin to wrap
+    /// * `name` - Name of the plugin, as shown in logs
+    /// * `frame_fitter` - Makes the plugin's frames fit the matrix
+    /// * `source_watcher` - Watches the plugin's WASM file for changes
+    ///
+    pub(crate) fn new(
+        plugin: Plugin<'static>,
+        name: String,
+        frame_fitter: FrameFitter,
+        source_watcher: SourceWatcher,
+    ) -> Self {
+        Self {
+            plugin,
+            name,
+            frame_fitter,
+            last_frame_shape: None,
+            warned_changes_outside: false,
+            source_watcher,
+        }
+    }
+
+    /// Get the name of the plugin
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Check if the plugin's WASM file has been changed since the plugin was loaded
+    pub(crate) fn is_outdated(&self) -> bool {
+        self.source_watcher.is_outdated()
+    }
+
+    /// Check if the plugin's WASM file has been changed and has finished being written, so that the plugin can be reloaded
+    ///
+    /// Each change is only reported once.
+    pub(crate) fn source_changed(&mut self) -> bool {
+        self.source_watcher.changed()
+    }
+
+    /// Read the metadata of the plugin, from its optional `metadata` export
+    ///
+    /// If the plugin declares a name, that name is used in logs from then on.
+    /// Plugins without metadata, or with malformed metadata, are treated as if they declared nothing.
+    pub(crate) fn read_metadata(&mut self) -> PluginMetadata {
+        if !self.plugin.has_function("metadata") {
+            return PluginMetadata::default();
+        }
+
+        let file_name = self.name.clone();
+        let metadata = match self.plugin.call("metadata", "") {
+            Ok(result) => match from_slice::<PluginMetadata>(result) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    log::warn!("Received malformed metadata from plugin \"{file_name}\". The metadata will be ignored.");
+                    log::debug!("Failed with the following error: {e:?}");
+                    return PluginMetadata::default();
+                }
+            },
+            Err(e) => {
+                log::warn!("Unable to retrieve metadata from plugin \"{file_name}\". The metadata will be ignored.");
+                log::debug!(
+                    "Received the following error while retrieving metadata from plugin: {e:?}"
+                );
+                return PluginMetadata::default();
+            }
+        };
+
+        if let Some(name) = &metadata.name {
+            self.name = name.clone();
+        }
+
+        // Describe the plugin with whatever it has declared
+        let mut description = format!("Plugin \"{file_name}\" is \"{}\"", self.name);
+        if let Some(version) = &metadata.version {
+            description.push_str(&format!(" version {version}"));
+        }
+        if let Some(author) = &metadata.author {
+            description.push_str(&format!(" by {author}"));
+        }
+        log::info!("{description}.");
+        if let Some(plugin_description) = &metadata.description {
+            log::info!("{plugin_description}");
+        }
+
+        metadata
+    }
+
+    /// Call the setup function of the plugin
+    pub(crate) fn setup(&mut self) {
+        let plugin_name = &self.name;
+        match self.plugin.call("setup", "") {
+            Ok(_) => {
+                log::info!("Successfully set up plugin \"{plugin_name}\".");
+            }
+            Err(e) => {
+                log::warn!("Unable to set up plugin \"{plugin_name}\".");
+                log::debug!("Received the following error while setting up the plugin: {e:?}");
+            }
+        };
+    }
+
+    /// Call the optional resume function of the plugin, when it is kept running for another turn
+    pub(crate) fn resume(&mut self) {
+        let plugin_name = &self.name;
+        if !self.plugin.has_function("resume") {
+            log::info!("Resuming plugin \"{plugin_name}\".");
+            return;
+        }
+
+        match self.plugin.call("resume", "") {
+            Ok(_) => {
+                log::info!("Successfully resumed plugin \"{plugin_name}\".");
+            }
+            Err(e) => {
+                log::warn!("Unable to resume plugin \"{plugin_name}\".");
+                log::debug!("Received the following error while resuming the plugin: {e:?}");
+            }
+        };
+    }
+
+    /// Call the optional teardown function of the plugin, before it leaves the playlist
+    ///
+    /// The plugin is given the reason as JSON, so that it can flush any state it is holding.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the plugin is leaving the playlist
+    ///
+    pub(crate) fn teardown(&mut self, reason: TeardownReason) {
+        if !self.plugin.has_function("teardown") {
+            return;
+        }
+
+        // Serializing the context cannot fail, since it only holds the reason
+        let input = serde_json::to_string(&TeardownContext::new(reason)).unwrap_or_default();
+
+        let plugin_name = &self.name;
+        match self.plugin.call("teardown", input) {
+            Ok(_) => {
+                log::info!("Successfully tore down plugin \"{plugin_name}\".");
+            }
+            Err(e) => {
+                log::warn!("Unable to tear down plugin \"{plugin_name}\".");
+                log::debug!("Received the following error while tearing down the plugin: {e:?}");
+            }
+        };
+    }
+
+    /// Call the update function of the plugin, and get the next update to the matrix
+    ///
+    /// Plugins may return either a JSON frame or a binary frame, with either the whole matrix or only the pixels that changed.
+    /// Returns `Ok(None)` if the plugin has no more updates to give.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_context` - Information about the current frame, which is given to the plugin as JSON
+    ///
+    pub(crate) fn update(
+        &mut self,
+        frame_context: &FrameContext,
+    ) -> Result<Option<MatrixUpdate>, ()> {
+        let plugin_name = &self.name;
+
+        // Serializing the context cannot fail, since it only holds numbers
+        let input = serde_json::to_string(frame_context).unwrap_or_default();
+
+        let update_result = match self.plugin.call("update", input) {
+            Ok(result) => result,
+            Err(e) => {
+                log::error!("Unable to retrieve state update from plugin \"{plugin_name}\"");
+                log::debug!(
+                    "Received the following error while retrieving state update from plugin: {e:?}"
+                );
+                return Err(());
+            }
+        };
+
+        let matrix_update = if is_binary_frame(update_result) {
+            // Read the packed frame directly
+            parse_binary_frame(plugin_name, update_result)?
+        } else {
+            match parse_json_update(plugin_name, update_result)? {
+                Some(matrix_update) => matrix_update,
+                None => return Ok(None),
+            }
+        };
+
+        let matrix_state = match matrix_update {
+            MatrixUpdate::Full(matrix_state) => matrix_state,
+            MatrixUpdate::Delta(changes) => {
+                return Ok(Some(MatrixUpdate::Delta(self.check_changes(changes))))
+            }
+        };
+
+        // Let the user know whenever the plugin starts returning frames of a different shape
+        let frame_shape = FrameShape::of(&matrix_state);
+        if !self.frame_fitter.matches(&frame_shape) && self.last_frame_shape != Some(frame_shape) {
+            let (width, height) = self.frame_fitter.dimensions();
+            log::warn!(
+                "Plugin \"{plugin_name}\" returned a {frame_shape} frame for a {width}x{height} matrix."
+            );
+            let policy = self.frame_fitter.policy();
+            if !matches!(policy, FitPolicy::Reject | FitPolicy::Pad) {
+                log::info!("Fitting the frame to the matrix with the {policy:?} fit policy.");
+            }
+        }
+        self.last_frame_shape = Some(frame_shape);
+
+        match self.frame_fitter.fit(matrix_state) {
+            Ok(matrix_state) => Ok(Some(MatrixUpdate::Full(matrix_state))),
+            Err(_) => {
+                log::error!(
+                    "The frame from plugin \"{plugin_name}\" does not fit the matrix under the {:?} fit policy.",
+                    self.frame_fitter.policy()
+                );
+                Err(())
+            }
+        }
+    }
+
+    /// Drop any pixel changes that fall outside of the matrix
+    ///
+    /// # Arguments
+    ///
+    /// * `changes` - The pixel changes returned by the plugin
+    ///
+    fn check_changes(&mut self, mut changes: Vec<PixelChange>) -> Vec<PixelChange> {
+        let (width, height) = self.frame_fitter.dimensions();
+        let change_count = changes.len();
+        changes.retain(|change| change.x < width && change.y < height);
+
+        // Only warn the first time, since the plugin is likely to keep doing this
+        if changes.len() < change_count && !self.warned_changes_outside {
+            log::warn!(
+                "Plugin \"{}\" changed pixels outside of the {width}x{height} matrix. These changes will be ignored.",
+                self.name
+            );
+            self.warned_changes_outside = true;
+        }
+
+        changes
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Deserialize;
+
+/// Information that a plugin can give about itself through its `metadata` export
+///
+/// Every field is optional, so plugins only need to declare what is relevant to them.
+#[derive(Deserialize, Default)]
+pub(crate) struct PluginMetadata {
+    /// Name of the plugin, used in logs instead of the file name
+    pub(crate) name: Option<String>,
+
+    /// Version of the plugin
+    pub(crate) version: Option<String>,
+
+    /// Author of the plugin
+    pub(crate) author: Option<String>,
+
+    /// Short description of what the plugin shows
+    pub(crate) description: Option<String>,
+
+    /// Smallest matrix width that the plugin supports
+    pub(crate) min_width: Option<usize>,
+
+    /// Largest matrix width that the plugin supports
+    pub(crate) max_width: Option<usize>,
+
+    /// Smallest matrix height that the plugin supports
+    pub(crate) min_height: Option<usize>,
+
+    /// Largest matrix height that the plugin supports
+    pub(crate) max_height: Option<usize>,
+
+    /// Framerate that the plugin was designed to run at
+    pub(crate) fps: Option<f32>,
+
+    /// Host functions that the plugin needs
+    #[serde(default)]
+    pub(crate) host_functions: Vec<String>,
+
+    /// Network hosts that the plugin needs to access
+    #[serde(default)]
+    pub(crate) allowed_hosts: Vec<String>,
+
+    /// Paths on the plugin filesystem that the plugin needs to access
+    #[serde(default)]
+    pub(crate) paths: Vec<String>,
+}
+
+/// What the host offers to a plugin, for checking a plugin's metadata against
+pub(crate) struct HostCapabilities<'a> {
+    /// Width of the matrix, in number of LEDs
+    pub(crate) width: usize,
+
+    /// Height of the matrix, in number of LEDs
+    pub(crate) height: usize,
+
+    /// Target framerate of the matrix
+    pub(crate) fps: f32,
+
+    /// Names of the functions that the host provides to plugins
+    pub(crate) host_functions: Vec<&'a str>,
+
+    /// Network hosts that plugins are allowed to access
+    pub(crate) allowed_hosts: &'a [String],
+
+    /// Paths on the plugin filesystem that are mapped from the host
+    pub(crate) paths: Vec<&'a str>,
+}
+
+impl PluginMetadata {
+    /// Check that the plugin can run with what the host offers
+    ///
+    /// Problems that stop the plugin from working are logged as errors, and make this return an error.
+    /// Problems that the plugin may be able to work around are logged as warnings.
+    ///
+    /// # Arguments
+    ///
+    /// * `plugin_name` - Name of the plugin, as shown in logs
+    /// * `host` - What the host offers to the plugin
+    ///
+    pub(crate) fn check_compatibility(
+        &self,
+        plugin_name: &str,
+        host: &HostCapabilities,
+    ) -> Result<(), ()> {
+        let mut compatible = true;
+
+        // Check that the matrix is a size that the plugin supports
+        let width_supported = self.min_width.is_none_or(|min| host.width >= min)
+            && self.max_width.is_none_or(|max| host.width <= max);
+        let height_supported = self.min_height.is_none_or(|min| host.height >= min)
+            && self.max_height.is_none_or(|max| host.height <= max);
+        if !width_supported || !height_supported {
+            log::error!(
+                "Plugin \"{plugin_name}\" supports widths of {} and heights of {}, but the matrix is {}x{}.",
+                describe_range(self.min_width, self.max_width),
+                describe_range(self.min_height, self.max_height),
+                host.width,
+                host.height
+            );
+            compatible = false;
+        }
+
+        // Check that the host provides every function the plugin needs
+        for host_function in &self.host_functions {
+            if !host.host_functions.contains(&host_function.as_str()) {
+                log::error!(
+                    "Plugin \"{plugin_name}\" needs the host function \"{host_function}\", which this version of Matricks does not provide."
+                );
+                compatible = false;
+            }
+        }
+
+        // The plugin may still run without its preferred framerate or permissions, so only warn about these
+        if let Some(fps) = self.fps {
+            if (fps - host.fps).abs() > f32::EPSILON {
+                log::warn!(
+                    "Plugin \"{plugin_name}\" was designed to run at {fps} FPS, but the matrix is running at {} FPS.",
+                    host.fps
+                );
+            }
+        }
+        for allowed_host in &self.allowed_hosts {
+            if !host.allowed_hosts.contains(allowed_host) {
+                log::warn!(
+                    "Plugin \"{plugin_name}\" needs to access host \"{allowed_host}\", which has not been allowed. Use --allow-host to allow it."
+                );
+            }
+        }
+        for path in &self.paths {
+            if !host.paths.contains(&path.as_str()) {
+                log::warn!(
+                    "Plugin \"{plugin_name}\" needs to access path \"{path}\", which has not been mapped. Use --map-path to map it."
+                );
+            }
+        }
+
+        if compatible {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// Describe a range of matrix sizes for logs
+///
+/// # Arguments
+///
+/// * `min` - The smallest size in the range, if there is one
+/// * `max` - The largest size in the range, if there is one
+///
+fn describe_range(min: Option<usize>, max: Option<usize>) -> String {
+    match (min, max) {
+        (Some(min), Some(max)) => format!("{min}-{max} LEDs"),
+        (Some(min), None) => format!("at least {min} LEDs"),
+        (None, Some(max)) => format!("at most {max} LEDs"),
+        (None, None) => String::from("any size"),
+    }
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::time::SystemTime;
+
+/// Watches the WASM file of a plugin for changes, so that the plugin can be reloaded
+pub(crate) struct SourceWatcher {
+    /// Path to the plugin's WASM file
+    path: String,
+
+    /// Modified time of the file when the plugin was loaded
+    loaded_modified: Option<SystemTime>,
+
+    /// Modified time of the file at the last check, if it differed from when the plugin was loaded
+    pending_modified: Option<SystemTime>,
+}
+
+impl SourceWatcher {
+    /// Start watching a plugin's WASM file
+    ///
+    /// This should be created before the file is read, so that changes made while reading it are not missed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the plugin's WASM file
+    ///
+    pub(crate) fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            loaded_modified: modified_time(path),
+            pending_modified: None,
+        }
+    }
+
+    /// Check if the file has been changed since the plugin was loaded
+    pub(crate) fn is_outdated(&self) -> bool {
+        modified_time(&self.path) != self.loaded_modified
+    }
+
+    /// Check if the file has been changed since the plugin was loaded, and has stopped changing
+    ///
+    /// The file must have the same modified time at two checks in a row, so that a file which is still being copied is not loaded.
+    /// Each change is only reported once.
+    pub(crate) fn changed(&mut self) -> bool {
+        // A missing file cannot be reloaded, so wait for it to come back
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.loaded_modified {
+            self.pending_modified = None;
+            return false;
+        }
+
+        if modified == self.pending_modified {
+            self.loaded_modified = modified;
+            self.pending_modified = None;
+            true
+        } else {
+            self.pending_modified = modified;
+            false
+        }
+    }
+}
+
+/// Get the modified time of a file, if it exists
+///
+/// # Arguments
+///
+/// * `path` - Path to the file
+///
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
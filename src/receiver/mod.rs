@@ -0,0 +1,4 @@
+pub(crate) mod receiver_artnet;
+pub(crate) mod receiver_ddp;
+pub mod receiver_protocol;
+pub(crate) mod receiver_sacn;
@@ -0,0 +1,119 @@
+use crate::output::output_sink::PIXELS_PER_UNIVERSE;
+use crate::receiver::receiver_protocol::{copy_universe, ReceiverProtocol};
+
+/// UDP port that Art-Net nodes listen on
+const ARTNET_PORT: u16 = 6454;
+
+/// Identifier that starts every Art-Net packet
+const ARTNET_ID: [u8; 8] = *b"Art-Net\0";
+
+/// Op code of an ArtDmx packet
+const ARTNET_OP_DMX: u16 = 0x5000;
+
+/// Length of an ArtDmx packet with no data, in bytes
+const ARTNET_DMX_HEADER_LENGTH: usize = 18;
+
+/// Port-address that frames start on if none is configured
+const ARTNET_DEFAULT_START_PORT_ADDRESS: u16 = 0;
+
+/// Receives frames from an Art-Net controller as ArtDmx packets
+///
+/// The frame is read from universes of 170 RGB pixels each, starting from the configured port-address.
+/// A frame is shown whenever the last universe of the frame is received.
+pub(crate) struct ArtNetReceiver {
+    /// Port-address that the first pixels of the frame are received on
+    start_port_address: u16,
+
+    /// Number of universes the frame is split across
+    universe_count: usize,
+}
+
+impl ArtNetReceiver {
+    /// Create a new Art-Net receiver
+    ///
+    /// # Arguments
+    ///
+    /// * `start_port_address` - Port-address that the first pixels of the frame are received on
+    /// * `pixel_count` - Number of pixels in a frame
+    ///
+    pub(crate) fn new(start_port_address: Option<u16>, pixel_count: usize) -> Self {
+        Self {
+            start_port_address: start_port_address.unwrap_or(ARTNET_DEFAULT_START_PORT_ADDRESS),
+            universe_count: pixel_count.div_ceil(PIXELS_PER_UNIVERSE),
+        }
+    }
+}
+
+impl ReceiverProtocol for ArtNetReceiver {
+    fn default_port(&self) -> u16 {
+        ARTNET_PORT
+    }
+
+    fn handle_packet(&mut self, packet: &[u8], frame: &mut [u8]) -> bool {
+        // Ignore anything that is not an ArtDmx packet, including ArtSync
+        if packet.len() < ARTNET_DMX_HEADER_LENGTH
+            || packet[..8] != ARTNET_ID
+            || packet[8..10] != ARTNET_OP_DMX.to_le_bytes()
+        {
+            return false;
+        }
+
+        // Ignore universes that are not part of the frame
+        let port_address = u16::from_le_bytes([packet[14], packet[15]]) & 0x7fff;
+        if port_address < self.start_port_address
+            || (port_address - self.start_port_address) as usize >= self.universe_count
+        {
+            return false;
+        }
+        let universe_index = (port_address - self.start_port_address) as usize;
+
+        let data_length = u16::from_be_bytes([packet[16], packet[17]]) as usize;
+        let data_end = (ARTNET_DMX_HEADER_LENGTH + data_length).min(packet.len());
+        copy_universe(
+            universe_index,
+            &packet[ARTNET_DMX_HEADER_LENGTH..data_end],
+            frame,
+        );
+
+        universe_index == self.universe_count - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an Art-Net packet with some DMX data
+    fn test_packet(op_code: u16, port_address: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = ARTNET_ID.to_vec();
+        packet.extend(op_code.to_le_bytes());
+        packet.extend([0, 14, 1, 0]);
+        packet.extend(port_address.to_le_bytes());
+        packet.extend((data.len() as u16).to_be_bytes());
+        packet.extend(data);
+        packet
+    }
+
+    #[test]
+    fn ignores_packets_that_are_not_dmx_data() {
+        let mut receiver = ArtNetReceiver::new(None, 2);
+        let mut frame = vec![0; 6];
+
+        // ArtSync has its own op code
+        assert!(!receiver.handle_packet(&test_packet(0x5200, 0, &[1; 6]), &mut frame));
+        assert!(!receiver.handle_packet(&test_packet(ARTNET_OP_DMX, 0, &[1; 6])[..17], &mut frame));
+        assert_eq!(frame, [0; 6]);
+
+        assert!(receiver.handle_packet(&test_packet(ARTNET_OP_DMX, 0, &[1; 6]), &mut frame));
+        assert_eq!(frame, [1; 6]);
+    }
+
+    #[test]
+    fn ignores_port_addresses_outside_of_the_frame() {
+        let mut receiver = ArtNetReceiver::new(Some(4), 2);
+        let mut frame = vec![0; 6];
+        assert!(!receiver.handle_packet(&test_packet(ARTNET_OP_DMX, 3, &[1; 6]), &mut frame));
+        assert!(!receiver.handle_packet(&test_packet(ARTNET_OP_DMX, 5, &[1; 6]), &mut frame));
+        assert_eq!(frame, [0; 6]);
+    }
+}
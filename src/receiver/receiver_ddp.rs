@@ -0,0 +1,179 @@
+use crate::receiver::receiver_protocol::ReceiverProtocol;
+
+/// UDP port that DDP devices listen on
+const DDP_PORT: u16 = 4048;
+
+/// Bits of the DDP header flags that hold the protocol version
+const DDP_FLAG_VERSION_MASK: u8 = 0xc0;
+
+/// DDP header flag marking the protocol version
+const DDP_FLAG_VERSION_1: u8 = 0x40;
+
+/// DDP header flag marking that the header includes a timecode
+const DDP_FLAG_TIMECODE: u8 = 0x10;
+
+/// DDP header flag telling the device to display the data it has received
+const DDP_FLAG_PUSH: u8 = 0x01;
+
+/// DDP header flags marking a query or a reply to a query, which do not carry pixel data
+const DDP_FLAG_QUERY_OR_REPLY: u8 = 0x06;
+
+/// DDP data type of 8-bit RGB pixels
+const DDP_TYPE_RGB8: u8 = 0x0b;
+
+/// Length of a DDP header, in bytes
+const DDP_HEADER_LENGTH: usize = 10;
+
+/// Length of a DDP header that includes a timecode, in bytes
+const DDP_TIMECODE_HEADER_LENGTH: usize = 14;
+
+/// Receives frames from a DDP source
+///
+/// Pixel data is written at the offset given in each packet, and the frame is shown when a packet has the push flag set.
+pub(crate) struct DdpReceiver {}
+
+impl DdpReceiver {
+    /// Create a new DDP receiver
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ReceiverProtocol for DdpReceiver {
+    fn default_port(&self) -> u16 {
+        DDP_PORT
+    }
+
+    fn handle_packet(&mut self, packet: &[u8], frame: &mut [u8]) -> bool {
+        if packet.len() < DDP_HEADER_LENGTH
+            || packet[0] & DDP_FLAG_VERSION_MASK != DDP_FLAG_VERSION_1
+        {
+            return false;
+        }
+        let flags = packet[0];
+
+        // Only pixel data in the format that frames are stored in can be shown
+        if flags & DDP_FLAG_QUERY_OR_REPLY != 0 || packet[2] != DDP_TYPE_RGB8 {
+            return false;
+        }
+
+        let header_length = if flags & DDP_FLAG_TIMECODE != 0 {
+            DDP_TIMECODE_HEADER_LENGTH
+        } else {
+            DDP_HEADER_LENGTH
+        };
+        if packet.len() < header_length {
+            return false;
+        }
+
+        // Copy as much of the data as fits in the frame
+        let offset = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]) as usize;
+        let data_length = u16::from_be_bytes([packet[8], packet[9]]) as usize;
+        let data = &packet[header_length..(header_length + data_length).min(packet.len())];
+        if offset < frame.len() {
+            let length = data.len().min(frame.len() - offset);
+            frame[offset..offset + length].copy_from_slice(&data[..length]);
+        }
+
+        flags & DDP_FLAG_PUSH != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_data_after_a_timecode_header() {
+        let mut packet = vec![
+            DDP_FLAG_VERSION_1 | DDP_FLAG_TIMECODE | DDP_FLAG_PUSH,
+            1,
+            DDP_TYPE_RGB8,
+            0x01,
+        ];
+        packet.extend(3u32.to_be_bytes());
+        packet.extend(3u16.to_be_bytes());
+        packet.extend([0xde, 0xad, 0xbe, 0xef]);
+        packet.extend([1, 2, 3]);
+
+        let mut receiver = DdpReceiver::new();
+        let mut frame = vec![0; 9];
+        assert!(receiver.handle_packet(&packet, &mut frame));
+        assert_eq!(frame, [0, 0, 0, 1, 2, 3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ignores_other_versions_and_short_packets() {
+        let mut receiver = DdpReceiver::new();
+        let mut frame = vec![0; 3];
+        let packet = [
+            0x80 | DDP_FLAG_PUSH,
+            1,
+            DDP_TYPE_RGB8,
+            0x01,
+            0,
+            0,
+            0,
+            0,
+            0,
+            3,
+            1,
+            2,
+            3,
+        ];
+        assert!(!receiver.handle_packet(&packet, &mut frame));
+        assert!(!receiver.handle_packet(&packet[..DDP_HEADER_LENGTH - 1], &mut frame));
+
+        // A timecode header that is cut short is ignored too
+        let packet = [
+            DDP_FLAG_VERSION_1 | DDP_FLAG_TIMECODE,
+            1,
+            DDP_TYPE_RGB8,
+            0x01,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        assert!(!receiver.handle_packet(&packet, &mut frame));
+        assert_eq!(frame, [0; 3]);
+    }
+
+    /// Make a DDP packet that writes one pixel at the start of the frame
+    fn test_packet(flags: u8, data_type: u8) -> Vec<u8> {
+        let mut packet = vec![flags, 1, data_type, 0x01];
+        packet.extend(0u32.to_be_bytes());
+        packet.extend(3u16.to_be_bytes());
+        packet.extend([1, 2, 3]);
+        packet
+    }
+
+    #[test]
+    fn ignores_queries_and_replies() {
+        let mut receiver = DdpReceiver::new();
+        let mut frame = vec![0; 3];
+        for flag in [0x02, 0x04] {
+            let packet = test_packet(DDP_FLAG_VERSION_1 | DDP_FLAG_PUSH | flag, DDP_TYPE_RGB8);
+            assert!(!receiver.handle_packet(&packet, &mut frame));
+        }
+        assert_eq!(frame, [0; 3]);
+    }
+
+    #[test]
+    fn ignores_data_types_other_than_rgb8() {
+        let mut receiver = DdpReceiver::new();
+        let mut frame = vec![0; 3];
+        for data_type in [0x00, 0x1b, 0x0a] {
+            let packet = test_packet(DDP_FLAG_VERSION_1 | DDP_FLAG_PUSH, data_type);
+            assert!(!receiver.handle_packet(&packet, &mut frame));
+        }
+        assert_eq!(frame, [0; 3]);
+
+        let packet = test_packet(DDP_FLAG_VERSION_1 | DDP_FLAG_PUSH, DDP_TYPE_RGB8);
+        assert!(receiver.handle_packet(&packet, &mut frame));
+        assert_eq!(frame, [1, 2, 3]);
+    }
+}
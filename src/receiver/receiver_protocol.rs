@@ -0,0 +1,205 @@
+use crate::clargs::{MatricksConfigArgs, ReceiveProtocol};
+use crate::matrix::matrix_control::MatrixController;
+use crate::matrix::matrix_state::MatrixState;
+use crate::output::output_sink::PIXELS_PER_UNIVERSE;
+use crate::receiver::receiver_artnet::ArtNetReceiver;
+use crate::receiver::receiver_ddp::DdpReceiver;
+use crate::receiver::receiver_sacn::SacnReceiver;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Largest UDP packet that any of the receivers will accept
+const RECEIVER_MAX_PACKET_LENGTH: usize = 2048;
+
+/// How long to wait for a packet before checking if Matricks has been asked to quit
+const RECEIVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A network protocol that frames can be received over
+pub(crate) trait ReceiverProtocol {
+    /// Get the standard UDP port of the protocol
+    fn default_port(&self) -> u16;
+
+    /// Prepare the socket that packets will be received on
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - The socket that packets will be received on
+    ///
+    fn prepare_socket(&self, _socket: &UdpSocket) {}
+
+    /// Copy the pixel data in a packet into a frame
+    ///
+    /// Returns true if the frame is ready to be shown.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet that was received
+    /// * `frame` - The frame, as RGB bytes for each pixel in row-major order
+    ///
+    fn handle_packet(&mut self, packet: &[u8], frame: &mut [u8]) -> bool;
+}
+
+/// Create the receiver for a protocol
+///
+/// # Arguments
+///
+/// * `protocol` - The protocol to receive frames over
+/// * `config` - Information about how Matricks should run
+///
+fn new_receiver_protocol(
+    protocol: ReceiveProtocol,
+    config: &MatricksConfigArgs,
+) -> Box<dyn ReceiverProtocol> {
+    let pixel_count = config.matrix.width * config.matrix.height;
+    let start_universe = config.receiver.receive_universe;
+    match protocol {
+        ReceiveProtocol::Sacn => Box::new(SacnReceiver::new(start_universe, pixel_count)),
+        ReceiveProtocol::Artnet => Box::new(ArtNetReceiver::new(start_universe, pixel_count)),
+        ReceiveProtocol::Ddp => Box::new(DdpReceiver::new()),
+    }
+}
+
+/// Drive the matrix with frames received over the network
+///
+/// Pixels are received in row-major order, and are mapped onto the matrix the same way as plugin updates.
+/// Frames are received until Matricks is asked to quit or the matrix controller fails.
+///
+/// # Arguments
+///
+/// * `protocol` - The protocol to receive frames over
+/// * `config` - Information about how Matricks should run
+/// * `matrix` - The matrix controller to send received frames to
+/// * `shutdown_requested` - Set when Matricks has been asked to quit
+///
+pub(crate) fn receive_frames(
+    protocol: ReceiveProtocol,
+    config: &MatricksConfigArgs,
+    matrix: &mut MatrixController,
+    shutdown_requested: &AtomicBool,
+) {
+    let width = config.matrix.width;
+    let height = config.matrix.height;
+    if width == 0 || height == 0 {
+        log::error!("Cannot receive frames for a matrix that is {width}x{height} LEDs.");
+        return;
+    }
+    let mut receiver = new_receiver_protocol(protocol, config);
+
+    // Open the socket to listen on
+    let port = config
+        .receiver
+        .receive_port
+        .unwrap_or(receiver.default_port());
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::error!("Failed to listen for frames on port {port}.");
+            log::debug!("Failed with the following error: {e:?}");
+            return;
+        }
+    };
+    receiver.prepare_socket(&socket);
+
+    // Wake up regularly, so that Matricks can quit while no packets are arriving
+    if let Err(e) = socket.set_read_timeout(Some(RECEIVER_POLL_INTERVAL)) {
+        log::error!("Failed to set up the socket to listen on.");
+        log::debug!("Failed with the following error: {e:?}");
+        return;
+    }
+    log::info!("Listening for {protocol:?} frames on port {port}.");
+
+    let mut frame = vec![0; width * height * 3];
+    let mut packet = [0; RECEIVER_MAX_PACKET_LENGTH];
+    loop {
+        if shutdown_requested.load(Ordering::SeqCst) {
+            log::info!("Received a request to quit.");
+            return;
+        }
+
+        let packet_length = match socket.recv(&mut packet) {
+            Ok(length) => length,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Failed to receive packet.");
+                log::debug!("Failed with the following error: {e:?}");
+                continue;
+            }
+        };
+
+        if !receiver.handle_packet(&packet[..packet_length], &mut frame) {
+            continue;
+        }
+
+        // Convert the received RGB pixels to a matrix state
+        let new_matrix_state: MatrixState = frame
+            .chunks(width * 3)
+            .map(|row| {
+                row.chunks(3)
+                    .map(|pixel| [pixel[2], pixel[1], pixel[0], 0])
+                    .collect()
+            })
+            .collect();
+
+        match matrix.update(new_matrix_state) {
+            Ok(_) => { /* Do nothing, the new state sent without issue */ }
+            Err(_) => {
+                log::error!("Failed to update matrix controller.");
+                return;
+            }
+        }
+    }
+}
+
+/// Copy the pixels of a DMX universe into a frame
+///
+/// # Arguments
+///
+/// * `universe_index` - Position of the universe, counting from the first universe of the frame
+/// * `channels` - The DMX channel data of the universe
+/// * `frame` - The frame, as RGB bytes for each pixel in row-major order
+///
+pub(crate) fn copy_universe(universe_index: usize, channels: &[u8], frame: &mut [u8]) {
+    let universe_length = PIXELS_PER_UNIVERSE * 3;
+    let start = universe_index * universe_length;
+    if start >= frame.len() {
+        return;
+    }
+    let length = channels.len().min(universe_length).min(frame.len() - start);
+    frame[start..start + length].copy_from_slice(&channels[..length]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_universe_places_each_universe_after_the_last() {
+        let mut frame = vec![0; (PIXELS_PER_UNIVERSE + 2) * 3];
+        copy_universe(1, &[1, 2, 3, 4, 5, 6], &mut frame);
+        assert_eq!(
+            frame[..PIXELS_PER_UNIVERSE * 3],
+            [0; PIXELS_PER_UNIVERSE * 3]
+        );
+        assert_eq!(frame[PIXELS_PER_UNIVERSE * 3..], [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn copy_universe_ignores_channels_past_the_end_of_the_frame() {
+        // Channels beyond the 170 pixels of a universe, or beyond the frame, are dropped
+        let mut frame = vec![0; 6];
+        copy_universe(0, &[7; 512], &mut frame);
+        assert_eq!(frame, [7; 6]);
+
+        let mut frame = vec![0; PIXELS_PER_UNIVERSE * 3 + 3];
+        copy_universe(0, &[8; 512], &mut frame);
+        assert_eq!(frame[PIXELS_PER_UNIVERSE * 3..], [0; 3]);
+
+        let mut frame = vec![0; 6];
+        copy_universe(1, &[9; 6], &mut frame);
+        assert_eq!(frame, [0; 6]);
+    }
+}
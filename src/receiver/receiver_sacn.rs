@@ -0,0 +1,109 @@
+use crate::output::output_sink::PIXELS_PER_UNIVERSE;
+use crate::receiver::receiver_protocol::{copy_universe, ReceiverProtocol};
+use std::net::{Ipv4Addr, UdpSocket};
+
+/// UDP port that E1.31 receivers listen on
+const SACN_PORT: u16 = 5568;
+
+/// ACN packet identifier that starts the root layer of every E1.31 packet
+const SACN_PACKET_IDENTIFIER: [u8; 12] = *b"ASC-E1.17\0\0\0";
+
+/// Root layer vector for E1.31 data packets
+const SACN_VECTOR_ROOT_DATA: u32 = 0x0000_0004;
+
+/// Framing layer vector for E1.31 data packets
+const SACN_VECTOR_FRAMING_DATA: u32 = 0x0000_0002;
+
+/// DMP layer vector to set properties
+const SACN_VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+/// Framing layer option bit marking preview data, which should not be shown
+const SACN_OPTION_PREVIEW_DATA: u8 = 0x80;
+
+/// Length of an E1.31 data packet with no slots, in bytes
+const SACN_HEADER_LENGTH: usize = 126;
+
+/// Universe that frames start on if none is configured
+const SACN_DEFAULT_START_UNIVERSE: u16 = 1;
+
+/// Receives frames from E1.31 (sACN) sources
+///
+/// The frame is read from universes of 170 RGB pixels each, starting from the configured universe.
+/// A frame is shown whenever the last universe of the frame is received.
+pub(crate) struct SacnReceiver {
+    /// Universe that the first pixels of the frame are received on
+    start_universe: u16,
+
+    /// Number of universes the frame is split across
+    universe_count: usize,
+}
+
+impl SacnReceiver {
+    /// Create a new sACN receiver
+    ///
+    /// # Arguments
+    ///
+    /// * `start_universe` - Universe that the first pixels of the frame are received on
+    /// * `pixel_count` - Number of pixels in a frame
+    ///
+    pub(crate) fn new(start_universe: Option<u16>, pixel_count: usize) -> Self {
+        Self {
+            start_universe: start_universe.unwrap_or(SACN_DEFAULT_START_UNIVERSE),
+            universe_count: pixel_count.div_ceil(PIXELS_PER_UNIVERSE),
+        }
+    }
+}
+
+impl ReceiverProtocol for SacnReceiver {
+    fn default_port(&self) -> u16 {
+        SACN_PORT
+    }
+
+    fn prepare_socket(&self, socket: &UdpSocket) {
+        // Join the multicast group of every universe in the frame
+        for universe_index in 0..self.universe_count {
+            let universe = self.start_universe as usize + universe_index;
+            let [high, low] = (universe as u16).to_be_bytes();
+            let group = Ipv4Addr::new(239, 255, high, low);
+            if let Err(e) = socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED) {
+                log::warn!("Unable to join multicast group for universe {universe}. Only unicast packets will be received for it.");
+                log::debug!("Failed with the following error: {e:?}");
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &[u8], frame: &mut [u8]) -> bool {
+        // Ignore anything that is not an E1.31 data packet with DMX data
+        if packet.len() < SACN_HEADER_LENGTH
+            || packet[4..16] != SACN_PACKET_IDENTIFIER
+            || packet[18..22] != SACN_VECTOR_ROOT_DATA.to_be_bytes()
+            || packet[40..44] != SACN_VECTOR_FRAMING_DATA.to_be_bytes()
+            || packet[117] != SACN_VECTOR_DMP_SET_PROPERTY
+            || packet[125] != 0x00
+            || packet[112] & SACN_OPTION_PREVIEW_DATA != 0
+        {
+            return false;
+        }
+
+        // Ignore universes that are not part of the frame
+        let universe = u16::from_be_bytes([packet[113], packet[114]]);
+        if universe < self.start_universe
+            || (universe - self.start_universe) as usize >= self.universe_count
+        {
+            return false;
+        }
+        let universe_index = (universe - self.start_universe) as usize;
+
+        // The property value count includes the start code
+        let slot_count =
+            (u16::from_be_bytes([packet[123], packet[124]]) as usize).saturating_sub(1);
+        let slots_end = (SACN_HEADER_LENGTH + slot_count).min(packet.len());
+        copy_universe(
+            universe_index,
+            &packet[SACN_HEADER_LENGTH..slots_end],
+            frame,
+        );
+
+        universe_index == self.universe_count - 1
+    }
+}
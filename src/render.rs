@@ -0,0 +1,378 @@
+use crate::clargs::{MatricksConfigArgs, OutputKind, RenderArgs, RenderFormat};
+use crate::matrix::matrix_state::{apply_changes, MatrixState, MatrixUpdate};
+use crate::playlist::playlist_expand::expand_playlist;
+use crate::playlist::playlist_order::PlaylistOrderer;
+use crate::playlist::playlist_schedule::MAIN_PLAYLIST;
+use crate::plugin::plugin_context::{FrameContext, TeardownReason};
+use crate::plugin::plugin_host::PluginHost;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Run plugins and render their output to an animated image
+///
+/// # Arguments
+///
+/// * `args` - Information about how the plugins should be run and rendered
+///
+pub fn matricks_render(args: RenderArgs) {
+    let config = &args.matricks_config;
+
+    // Figure out which format to render to
+    let format = match args.format.or_else(|| format_from_path(&args.image_path)) {
+        Some(format) => format,
+        None => {
+            log::error!(
+                "Unable to determine the image format for path \"{}\". Use a .gif or .png extension, or set the format with --format.",
+                args.image_path
+            );
+            log::info!("Quitting Matricks.");
+            return;
+        }
+    };
+
+    warn_ignored_options(config);
+
+    // Setup everything the plugins need from the host
+    let plugin_host = PluginHost::new(config);
+
+    // Run each plugin in the playlist, collecting its frames at the size of the matrix
+    let mut frames: Vec<MatrixState> = vec![];
+    let mut matrix_state: MatrixState =
+        vec![vec![[0; 4]; config.matrix.width]; config.matrix.height];
+    let playlist = PlaylistOrderer::new(&config.plugin)
+        .next_pass(expand_playlist(MAIN_PLAYLIST, &config.plugin.plugin));
+    for playlist_item in playlist {
+        let mut plugin = match plugin_host.load(&playlist_item.entry) {
+            Ok(plugin) => plugin,
+            Err(_) => {
+                log::warn!("This plugin will be skipped.");
+                continue;
+            }
+        };
+
+        plugin.setup();
+
+        // Only render as many frames as the plugin would get to show under the time limit
+        let entry = &playlist_item.entry;
+        let time_limit_secs = entry.time_limit.or(config.plugin.time_limit);
+        let frame_limit = match time_limit_secs {
+            None => args.frames,
+            Some(time_limit) => args
+                .frames
+                .min((time_limit as f32 * config.matrix.fps).round() as usize),
+        };
+
+        // Frames are rendered as fast as possible, so give the plugin the times it would see when running live
+        let entry_fps = entry.frame_rate(config.matrix.fps);
+        let frame_time = Duration::from_nanos((1_000_000_000.0 / entry_fps).round() as u64);
+        let time_limit = time_limit_secs.map(Duration::from_secs);
+        let plugin_first_frame = frames.len();
+        let render_start_time = SystemTime::now();
+
+        // Running out of frames to render is treated like hitting the time limit
+        let mut teardown_reason = TeardownReason::TimeLimit;
+        for frame_index in 0.. {
+            if frames.len() - plugin_first_frame >= frame_limit {
+                break;
+            }
+
+            let elapsed = frame_time * frame_index;
+            let delta = if frame_index == 0 {
+                Duration::ZERO
+            } else {
+                frame_time
+            };
+            let frame_context = FrameContext::new(
+                frame_index as u64,
+                elapsed,
+                delta,
+                time_limit,
+                render_start_time + elapsed,
+            );
+
+            match plugin.update(&frame_context) {
+                Ok(Some(matrix_update)) => {
+                    match matrix_update {
+                        MatrixUpdate::Full(new_matrix_state) => matrix_state = new_matrix_state,
+                        MatrixUpdate::Delta(changes) => apply_changes(&mut matrix_state, &changes),
+                    }
+
+                    // Show the update for as many matrix frames as it would be shown for when running live
+                    let shown_until = ((frame_index + 1) as f32 * config.matrix.fps / entry_fps)
+                        .round() as usize;
+                    while frames.len() - plugin_first_frame < shown_until.min(frame_limit) {
+                        frames.push(matrix_state.clone());
+                    }
+                }
+                Ok(None) => {
+                    log::info!("Done with plugin \"{}\".", plugin.name());
+                    teardown_reason = TeardownReason::Finished;
+                    break;
+                }
+                Err(_) => {
+                    log::warn!("This plugin will be skipped.");
+                    teardown_reason = TeardownReason::Error;
+                    break;
+                }
+            }
+        }
+
+        // Hold the last frame if the plugin finished before its minimum duration
+        if let (TeardownReason::Finished, Some(min_duration)) =
+            (teardown_reason, entry.min_duration)
+        {
+            let min_frames = args
+                .frames
+                .min((min_duration as f32 * config.matrix.fps).round() as usize);
+            if let Some(last_frame) = frames.last().cloned() {
+                while frames.len() - plugin_first_frame < min_frames {
+                    frames.push(last_frame.clone());
+                }
+            }
+        }
+
+        plugin.teardown(teardown_reason);
+    }
+
+    if frames.is_empty() {
+        log::error!("No frames were rendered, so no image will be written.");
+        log::info!("Quitting Matricks.");
+        return;
+    }
+
+    // Encode the frames to the image, scaling each one up as it is written
+    let raster = Raster {
+        width: config.matrix.width,
+        height: config.matrix.height,
+        scale: args.scale,
+    };
+    let result = match format {
+        RenderFormat::Gif => write_gif(&args.image_path, &frames, raster, config.matrix.fps),
+        RenderFormat::Apng => write_apng(&args.image_path, &frames, raster, config.matrix.fps),
+    };
+
+    if result.is_ok() {
+        log::info!(
+            "Rendered {} frames to \"{}\".",
+            frames.len(),
+            args.image_path
+        );
+    }
+    log::info!("Quitting Matricks.");
+}
+
+/// Warn about any options that have no effect while rendering
+///
+/// # Arguments
+///
+/// * `config` - Information about how the plugins should be run
+///
+fn warn_ignored_options(config: &MatricksConfigArgs) {
+    if config.plugin.loop_plugins {
+        log::warn!("Plugins are not looped while rendering; each plugin will be rendered once.");
+    }
+    if config.receiver.receive.is_some() {
+        log::warn!("Frames cannot be received from the network while rendering. The receive options will be ignored.");
+    }
+    if config.matrix.output != [OutputKind::Ws281x] {
+        log::warn!("Frames are only written to the image while rendering. The output options will be ignored.");
+    }
+    if config.matrix.brightness_schedule.is_some() {
+        log::warn!(
+            "Frames are rendered without brightness. The brightness schedule will be ignored."
+        );
+    }
+    if !config.schedule.is_empty() {
+        log::warn!("Only the main playlist is rendered. The playlist schedule will be ignored.");
+    }
+    if config.plugin.watch {
+        log::warn!("Plugins are not watched for changes while rendering. The watch option will be ignored.");
+    }
+}
+
+/// The size of the frames of a rendered image
+#[derive(Clone, Copy)]
+struct Raster {
+    /// Width of the matrix, in number of LEDs
+    width: usize,
+
+    /// Height of the matrix, in number of LEDs
+    height: usize,
+
+    /// Size of each LED in the image, in pixels
+    scale: usize,
+}
+
+impl Raster {
+    /// Get the size of the image, in pixels (width, height)
+    fn dimensions(&self) -> (usize, usize) {
+        (self.width * self.scale, self.height * self.scale)
+    }
+
+    /// Draw a matrix state to an RGBA image, with each LED drawn as a square of pixels
+    ///
+    /// Pixels missing from the matrix state are drawn as black, and pixels outside of the matrix are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `matrix_state` - The matrix state to draw
+    ///
+    fn rasterize(&self, matrix_state: &MatrixState) -> Vec<u8> {
+        let (image_width, image_height) = self.dimensions();
+        let mut image = Vec::with_capacity(image_width * image_height * 4);
+        for y in 0..image_height {
+            for x in 0..image_width {
+                let [b, g, r, _] = matrix_state
+                    .get(y / self.scale)
+                    .and_then(|row| row.get(x / self.scale))
+                    .copied()
+                    .unwrap_or([0; 4]);
+                image.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        image
+    }
+}
+
+/// Guess the format of an image from its file extension
+///
+/// # Arguments
+///
+/// * `image_path` - Path to the image
+///
+fn format_from_path(image_path: &str) -> Option<RenderFormat> {
+    let extension = Path::new(image_path).extension()?.to_str()?;
+    match extension.to_lowercase().as_str() {
+        "gif" => Some(RenderFormat::Gif),
+        "png" | "apng" => Some(RenderFormat::Apng),
+        _ => None,
+    }
+}
+
+/// Create a file to write an image to
+///
+/// # Arguments
+///
+/// * `image_path` - Path to the image
+///
+fn create_image_file(image_path: &str) -> Result<BufWriter<File>, ()> {
+    match File::create(image_path) {
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(e) => {
+            log::error!("Failed to create image at path \"{image_path}\".");
+            log::debug!("Failed with the following error: {e:?}");
+            Err(())
+        }
+    }
+}
+
+/// Encode a set of frames to an animated GIF
+///
+/// # Arguments
+///
+/// * `image_path` - Path to write the GIF to
+/// * `frames` - The matrix states to encode
+/// * `raster` - The size of each frame
+/// * `fps` - The framerate of the animation
+///
+fn write_gif(image_path: &str, frames: &[MatrixState], raster: Raster, fps: f32) -> Result<(), ()> {
+    let (image_width, image_height) = raster.dimensions();
+    let (width, height) = match (u16::try_from(image_width), u16::try_from(image_height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => {
+            log::error!(
+                "The rendered image is too large to be stored as a GIF. Try a smaller scale."
+            );
+            return Err(());
+        }
+    };
+
+    let file = create_image_file(image_path)?;
+    let mut encoder = match gif::Encoder::new(file, width, height, &[]) {
+        Ok(encoder) => encoder,
+        Err(e) => {
+            log::error!("Failed to start encoding GIF.");
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+    };
+
+    if let Err(e) = encoder.set_repeat(gif::Repeat::Infinite) {
+        log::error!("Failed to set GIF to repeat.");
+        log::debug!("Failed with the following error: {e:?}");
+        return Err(());
+    }
+
+    // GIF frame delays are in hundredths of a second
+    let delay = ((100.0 / fps).round() as u16).max(1);
+
+    for matrix_state in frames {
+        let mut frame_data = raster.rasterize(matrix_state);
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut frame_data, 10);
+        frame.delay = delay;
+        if let Err(e) = encoder.write_frame(&frame) {
+            log::error!("Failed to write frame to GIF.");
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode a set of frames to an animated PNG
+///
+/// # Arguments
+///
+/// * `image_path` - Path to write the APNG to
+/// * `frames` - The matrix states to encode
+/// * `raster` - The size of each frame
+/// * `fps` - The framerate of the animation
+///
+fn write_apng(
+    image_path: &str,
+    frames: &[MatrixState],
+    raster: Raster,
+    fps: f32,
+) -> Result<(), ()> {
+    let (image_width, image_height) = raster.dimensions();
+    let file = create_image_file(image_path)?;
+    let mut encoder = png::Encoder::new(file, image_width as u32, image_height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    // APNG frame delays are given as a fraction of a second
+    let delay_ms = ((1000.0 / fps).round() as u16).max(1);
+
+    let header_result = encoder
+        .set_animated(frames.len() as u32, 0)
+        .and_then(|_| encoder.set_frame_delay(delay_ms, 1000))
+        .and_then(|_| encoder.write_header());
+    let mut writer = match header_result {
+        Ok(writer) => writer,
+        Err(e) => {
+            log::error!("Failed to start encoding APNG.");
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+    };
+
+    for matrix_state in frames {
+        if let Err(e) = writer.write_image_data(&raster.rasterize(matrix_state)) {
+            log::error!("Failed to write frame to APNG.");
+            log::debug!("Failed with the following error: {e:?}");
+            return Err(());
+        }
+    }
+
+    match writer.finish() {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Failed to finish encoding APNG.");
+            log::debug!("Failed with the following error: {e:?}");
+            Err(())
+        }
+    }
+}